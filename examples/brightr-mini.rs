@@ -0,0 +1,138 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! A cut-down version of the `brightr` tool, for places where its size
+//! matters, like small embedded images.
+//!
+//! This only does `get`, `set`, `up` and `down`, in percent or raw units, and
+//! parses its own arguments rather than using clap, so it needs nothing but
+//! the library. It always goes through logind, and there's no config file,
+//! curve or policy support; anywhere those matter, use the full tool.
+
+use brightr::{adjust::Adjustment, Brightness};
+use std::{env, process::ExitCode};
+
+const USAGE: &str = "\
+usage: brightr-mini [-n NAME] [-r] COMMAND
+
+commands:
+  get          print the current brightness
+  set VALUE    set the brightness
+  up AMOUNT    increase the brightness
+  down AMOUNT  decrease the brightness
+
+Values are in percent, or raw units with -r. A `%` or `r` suffix gives the
+unit for one value.";
+
+/// A command from the command line.
+#[derive(Debug)]
+enum Command {
+    Get,
+    Set(Value),
+    Up(Value),
+    Down(Value),
+}
+
+/// A brightness value or amount from the command line.
+#[derive(Copy, Clone, Debug)]
+struct Value {
+    amount: u32,
+    raw: bool,
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("brightr-mini: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Does everything `main` does, minus reporting errors.
+fn run() -> Result<(), String> {
+    let mut name = None;
+    let mut raw = false;
+    let mut args = env::args().skip(1);
+    let command = loop {
+        match args.next().as_deref() {
+            Some("-n" | "--name") => {
+                name = Some(args.next().ok_or("-n needs a device name")?);
+            }
+            Some("-r" | "--raw") => raw = true,
+            Some("-h" | "--help") => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            Some(command) => break command.to_owned(),
+            None => return Err(format!("missing command\n\n{USAGE}")),
+        }
+    };
+    let value = args
+        .next()
+        .map(|text| parse_value(&text, raw))
+        .transpose()?;
+    if let Some(extra) = args.next() {
+        return Err(format!("unexpected argument `{extra}`"));
+    }
+    let command = match (command.as_str(), value) {
+        ("get", None) => Command::Get,
+        ("set", Some(value)) => Command::Set(value),
+        ("up", Some(value)) => Command::Up(value),
+        ("down", Some(value)) => Command::Down(value),
+        ("get", Some(_)) => return Err("get takes no value".to_owned()),
+        ("set" | "up" | "down", None) => {
+            return Err(format!("{command} needs a value"));
+        }
+        _ => return Err(format!("unknown command `{command}`\n\n{USAGE}")),
+    };
+
+    let (bl, current) = match name {
+        Some(name) => brightr::use_specific_backlight(name),
+        None => brightr::find_first_backlight(),
+    }
+    .map_err(|e| e.to_string())?;
+    let adjustment = match command {
+        Command::Get => {
+            if raw {
+                println!("{current}/{}", bl.max);
+            } else {
+                println!("{}/100", bl.percent_from_raw(current, 1.));
+            }
+            return Ok(());
+        }
+        Command::Set(value) => Adjustment::Set(value.brightness()),
+        Command::Up(by) => Adjustment::Increase(by.brightness()),
+        Command::Down(by) => Adjustment::Decrease(by.brightness()),
+    };
+    let target = adjustment.apply(&bl, current);
+    brightr::connect_and_set_brightness(&bl, target)
+        .map_err(|e| format!("can't set brightness: {e}"))
+}
+
+/// Parses a value from the command line, in raw units if `raw` is set and
+/// the value has no unit suffix of its own.
+fn parse_value(text: &str, raw: bool) -> Result<Value, String> {
+    let (digits, raw) = if let Some(digits) = text.strip_suffix('%') {
+        (digits, false)
+    } else if let Some(digits) = text.strip_suffix('r') {
+        (digits, true)
+    } else {
+        (text, raw)
+    };
+    let amount = digits.parse().map_err(|_| format!("bad value `{text}`"))?;
+    Ok(Value { amount, raw })
+}
+
+impl Value {
+    /// Converts this into a `Brightness` for the library.
+    fn brightness(self) -> Brightness {
+        if self.raw {
+            Brightness::Raw(self.amount)
+        } else {
+            Brightness::Percent(f64::from(self.amount))
+        }
+    }
+}
@@ -0,0 +1,199 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The different ways we can change a display's brightness, and falling back
+//! between them.
+
+use brightr::{Backlight, SessionProxyBlocking};
+use log::debug;
+use serde::Deserialize;
+use std::{fmt, fs::OpenOptions};
+use zbus::blocking::Connection;
+
+/// Ways of changing the brightness.
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Ask logind to change the device's setting. This works in any desktop
+    /// environment, or none.
+    Logind,
+    /// Ask GNOME's settings daemon, so that GNOME's own slider and on-screen
+    /// display stay in sync.
+    Gnome,
+    /// Write to the device in sysfs directly. This needs write access to the
+    /// device, which usually means root or a udev rule.
+    Sysfs,
+    /// Use OpenBSD's wscons, through `wsconsctl`. This usually needs root.
+    #[cfg(target_os = "openbsd")]
+    Wscons,
+}
+
+/// Backends to try if the config doesn't say.
+#[cfg(not(target_os = "openbsd"))]
+pub const DEFAULT_CHAIN: &[Backend] = &[Backend::Logind];
+/// Backends to try if the config doesn't say.
+#[cfg(target_os = "openbsd")]
+pub const DEFAULT_CHAIN: &[Backend] = &[Backend::Wscons];
+
+impl Backend {
+    /// Name of the backend, as written in the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Logind => "logind",
+            Backend::Gnome => "gnome",
+            Backend::Sysfs => "sysfs",
+            #[cfg(target_os = "openbsd")]
+            Backend::Wscons => "wscons",
+        }
+    }
+
+    /// Checks whether this backend needs the system or session bus.
+    pub fn uses_dbus(self) -> bool {
+        match self {
+            Backend::Logind | Backend::Gnome => true,
+            Backend::Sysfs => false,
+            #[cfg(target_os = "openbsd")]
+            Backend::Wscons => false,
+        }
+    }
+
+    /// Checks whether this backend looks like it'll work for `bl`, without
+    /// changing anything.
+    pub fn probe(self, bl: &Backlight) -> anyhow::Result<()> {
+        match self {
+            Backend::Logind => {
+                let _ = Connection::system()?;
+            }
+            Backend::Gnome => {
+                brightr::gnome::get_brightness()?;
+            }
+            Backend::Sysfs => {
+                // Opening the file for writing tells us whether we're allowed
+                // to, but doesn't actually change it.
+                OpenOptions::new()
+                    .write(true)
+                    .open(bl.path().join("brightness"))?;
+            }
+            #[cfg(target_os = "openbsd")]
+            Backend::Wscons => {
+                brightr::wscons::get_brightness()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets `bl` to raw value `target` using this backend.
+    pub fn set(self, bl: &Backlight, target: u32) -> anyhow::Result<()> {
+        match self {
+            Backend::Logind => brightr::connect_and_set_brightness(bl, target)?,
+            Backend::Gnome => {
+                // A device whose whole range is 0 has no percentages to speak
+                // of.
+                if bl.max == 0 || target > bl.max {
+                    let max = bl.max;
+                    Err(brightr::Error::OutOfRange { value: target, max })?;
+                }
+                // GNOME's percentages are linear, and ignore our exponent.
+                let pct =
+                    (f64::from(target) * 100. / f64::from(bl.max)).round();
+                brightr::gnome::set_brightness(pct as u32)?
+            }
+            Backend::Sysfs => brightr::sysfs_set_brightness(bl, target)?,
+            // find_device sets things up so raw values are percentages.
+            #[cfg(target_os = "openbsd")]
+            Backend::Wscons => brightr::wscons::set_brightness(target)?,
+        }
+        Ok(())
+    }
+}
+
+/// Sets `bl` to raw value `target` using the first backend in `chain` that
+/// works, and returns the backend used.
+pub fn set(
+    chain: &[Backend],
+    bl: &Backlight,
+    target: u32,
+) -> Result<Backend, Failed> {
+    Writer::new(chain).set(bl, target)
+}
+
+/// Sets the brightness repeatedly using a chain of backends, like `set`, but
+/// keeps the connection to logind open between changes.
+#[derive(Debug)]
+pub struct Writer<'a> {
+    chain: &'a [Backend],
+    session: Option<SessionProxyBlocking<'static>>,
+}
+
+impl<'a> Writer<'a> {
+    /// Makes a writer that will try the backends in `chain`, in order. This
+    /// doesn't connect to anything until it's first used.
+    pub fn new(chain: &'a [Backend]) -> Self {
+        Writer {
+            chain,
+            session: None,
+        }
+    }
+
+    /// Sets `bl` to raw value `target` using the first backend that works,
+    /// and returns the backend used.
+    pub fn set(
+        &mut self,
+        bl: &Backlight,
+        target: u32,
+    ) -> Result<Backend, Failed> {
+        let mut failures = vec![];
+        for &backend in self.chain {
+            let result = match backend {
+                Backend::Logind => self.logind(bl, target),
+                _ => backend.set(bl, target),
+            };
+            match result {
+                Ok(()) => {
+                    debug!("set brightness using {} backend", backend.name());
+                    return Ok(backend);
+                }
+                Err(e) => {
+                    debug!("{} backend failed: {e:#}", backend.name());
+                    failures.push((backend, e));
+                }
+            }
+        }
+        Err(Failed(failures))
+    }
+
+    /// Sets `bl` to `target` through logind, connecting first if needed.
+    fn logind(&mut self, bl: &Backlight, target: u32) -> anyhow::Result<()> {
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => self.session.insert(brightr::connect_to_session()?),
+        };
+        brightr::set_brightness(session, bl, target)?;
+        Ok(())
+    }
+}
+
+/// Error from `set` when every backend failed, holding each one's reason.
+#[derive(Debug)]
+pub struct Failed(pub Vec<(Backend, anyhow::Error)>);
+
+impl fmt::Display for Failed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no backend could set the brightness:")?;
+        for (backend, e) in &self.0 {
+            write!(f, "\n  {}: {e:#}", backend.name())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Failed {}
+
+/// Finds the backend in `chain` that we'd use for `bl`, if any look usable.
+pub fn active(chain: &[Backend], bl: &Backlight) -> Option<Backend> {
+    chain
+        .iter()
+        .copied()
+        .find(|backend| backend.probe(bl).is_ok())
+}
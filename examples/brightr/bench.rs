@@ -0,0 +1,80 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The `bench` command, which measures how quickly devices respond.
+
+use anyhow::bail;
+use std::time::{Duration, Instant};
+
+/// Runs the `bench` command on each of the devices in `names`, reading and
+/// writing each one `iterations` times.
+pub fn run(names: &[String], iterations: usize) -> anyhow::Result<()> {
+    if names.is_empty() {
+        bail!(brightr::Error::EternalDarkness);
+    }
+    if iterations == 0 {
+        bail!("need at least one iteration");
+    }
+    // Connecting is a one-off cost that we don't want to count.
+    let session = brightr::connect_to_session();
+    for name in names {
+        println!("{name}:");
+
+        let mut reads = vec![];
+        let mut found = None;
+        for _ in 0..iterations {
+            let start = Instant::now();
+            match brightr::use_specific_backlight(name.as_str()) {
+                Ok(settings) => found = Some(settings),
+                Err(e) => {
+                    println!("  can't read: {e}");
+                    break;
+                }
+            }
+            reads.push(start.elapsed());
+        }
+        let Some((bl, current)) = found else {
+            continue;
+        };
+        println!("  read:  {}", percentiles(reads));
+
+        let session = match &session {
+            Ok(session) => session,
+            Err(e) => {
+                println!("  can't connect to logind: {e}");
+                continue;
+            }
+        };
+        let mut writes = vec![];
+        for _ in 0..iterations {
+            let start = Instant::now();
+            if let Err(e) = brightr::set_brightness(session, &bl, current) {
+                println!("  can't write: {e}");
+                break;
+            }
+            writes.push(start.elapsed());
+        }
+        if !writes.is_empty() {
+            println!("  write: {}", percentiles(writes));
+        }
+    }
+    Ok(())
+}
+
+/// Summarizes the distribution of some (non-empty) `timings`.
+fn percentiles(mut timings: Vec<Duration>) -> String {
+    timings.sort();
+    let pick = |p: usize| {
+        // Nearest-rank percentile.
+        let rank = (p * timings.len()).div_ceil(100).max(1);
+        timings[rank - 1].as_secs_f64() * 1000.
+    };
+    format!(
+        "p50 {:.2} ms, p90 {:.2} ms, p99 {:.2} ms, max {:.2} ms",
+        pick(50),
+        pick(90),
+        pick(99),
+        pick(100),
+    )
+}
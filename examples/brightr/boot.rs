@@ -0,0 +1,113 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The `apply-boot` command's system unit, which sets the brightness from the
+//! config file early in boot.
+//!
+//! Nobody is logged in that early, so logind won't change anything for us;
+//! the unit runs as root and writes to sysfs directly. It runs before most
+//! filesystems are mounted, too, so it can only use files on the root
+//! filesystem: the system-wide config file, and an installed `brightr`.
+
+use crate::{config, config::Config, Brightr};
+use anyhow::{bail, Context};
+use brightr::Backlight;
+use std::path::{Path, PathBuf};
+
+/// Directories an installed `brightr` can be run from at boot.
+const SYSTEM_DIRS: &[&str] = &[
+    "/usr/local/bin",
+    "/usr/local/sbin",
+    "/usr/bin",
+    "/usr/sbin",
+    "/bin",
+    "/sbin",
+];
+
+/// Prints a systemd unit that runs `apply-boot` on `bl` at boot, using the
+/// system-wide config file, with the same exponent and minimum as this run.
+/// The unit runs `binary`, if given, or else this program, as long as it's
+/// installed somewhere that's available that early.
+pub fn print_unit(
+    args: &Brightr,
+    binary: Option<&Path>,
+    bl: &Backlight,
+) -> anyhow::Result<()> {
+    let exe = match binary {
+        Some(binary) => {
+            if !binary.is_absolute() || !binary.is_file() {
+                bail!("{} isn't an absolute path to brightr", binary.display());
+            }
+            binary.to_owned()
+        }
+        None => installed_exe()?,
+    };
+    let path = config::system_path();
+    match Config::load_system() {
+        Ok(system) if system.boot.is_some() => (),
+        Ok(_) => eprintln!(
+            "warning: {} doesn't set `boot` yet, so the unit will fail until \
+             it does",
+            path.display()
+        ),
+        Err(e) => eprintln!("warning: the unit can't use its config: {e:#}"),
+    }
+    let name = &bl.name;
+    let subsystem = bl.subsystem.name();
+    print!(
+        "\
+[Unit]
+Description=Set {name} to its boot brightness
+DefaultDependencies=no
+After=systemd-udev-trigger.service systemd-backlight@{subsystem}:{name}.service
+Before=sysinit.target shutdown.target
+Conflicts=shutdown.target
+
+[Service]
+Type=oneshot
+Environment={}
+ExecStart={} --backend sysfs --name {} --exponent {} --min {} apply-boot
+
+[Install]
+WantedBy=sysinit.target
+",
+        quote(&format!("BRIGHTR_SYSTEM_CONFIG={}", path.display())),
+        quote(&exe.display().to_string()),
+        quote(name),
+        args.exponent,
+        args.min,
+    );
+    Ok(())
+}
+
+/// Finds this program, as long as it's installed in one of `SYSTEM_DIRS`,
+/// rather than somewhere like a home directory, which may not be mounted
+/// when the unit runs.
+fn installed_exe() -> anyhow::Result<PathBuf> {
+    let exe = std::env::current_exe().context("can't find own executable")?;
+    let installed = exe
+        .parent()
+        .is_some_and(|dir| SYSTEM_DIRS.iter().any(|s| dir == Path::new(s)));
+    if !installed {
+        bail!(
+            "{} isn't installed in a system directory, so it may not be \
+             there at boot; install it with \
+             `sudo install -m 755 {} /usr/local/bin/brightr`, and run that, or \
+             pass --binary",
+            exe.display(),
+            exe.display(),
+        );
+    }
+    Ok(exe)
+}
+
+/// Quotes `arg` for a systemd unit file, escaping quotes, backslashes and
+/// `%`, which systemd would otherwise take as the start of a specifier.
+fn quote(arg: &str) -> String {
+    let escaped = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%");
+    format!("\"{escaped}\"")
+}
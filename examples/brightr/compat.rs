@@ -0,0 +1,208 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Compatibility with other backlight tools' command lines, so that scripts
+//! and keybindings written for them keep working.
+//!
+//! When run under another tool's name, usually through a symlink, we read the
+//! command line the way that tool would, and turn it into the equivalent
+//! `brightr` command line before parsing it as usual.
+
+use std::{ffi::OsString, path::Path};
+
+/// Rewrites `argv` into the equivalent `brightr` command line if we were run
+/// as another tool, or returns it unchanged if not.
+pub fn translate(argv: Vec<OsString>) -> Result<Vec<OsString>, String> {
+    let program = argv
+        .first()
+        .and_then(|arg| Path::new(arg).file_name())
+        .and_then(|name| name.to_str());
+    match program {
+        Some("xbacklight") => xbacklight(&argv),
+        Some("light") => light(&argv),
+        _ => Ok(argv),
+    }
+}
+
+/// Translates an `xbacklight` command line, which is the flags `-get`,
+/// `-set N`, `-inc N` and `-dec N`, or the shorthands `=N`, `+N` and `-N`.
+/// Values are percentages, and may have fractions.
+///
+/// `-ctrl` picks a device, like `--name`, and `-time` fades over that many
+/// milliseconds, like `--smooth`. The other options for fades and X displays
+/// are accepted but ignored.
+fn xbacklight(argv: &[OsString]) -> Result<Vec<OsString>, String> {
+    let mut flags = vec![argv[0].clone()];
+    // With no operation, xbacklight prints the brightness.
+    let mut cmd = vec!["get".into(), "--bare".into()];
+    let mut args = argv[1..].iter();
+    while let Some(arg) = args.next() {
+        let arg = arg.to_str().ok_or("arguments must be UTF-8")?;
+        let (verb, value) = match arg {
+            "-get" | "-getf" => {
+                cmd = vec!["get".into(), "--bare".into()];
+                continue;
+            }
+            "-set" => ("set", next_value(&mut args, arg)?),
+            "-inc" => ("up", next_value(&mut args, arg)?),
+            "-dec" => ("down", next_value(&mut args, arg)?),
+            "-ctrl" => {
+                flags.push("--name".into());
+                flags.push(next_value(&mut args, arg)?.into());
+                continue;
+            }
+            "-time" => {
+                let ms = next_value(&mut args, arg)?;
+                flags.push(format!("--smooth={ms}").into());
+                continue;
+            }
+            "-steps" | "-fps" | "-display" => {
+                next_value(&mut args, arg)?;
+                continue;
+            }
+            _ => {
+                let verb = match arg.chars().next() {
+                    Some('=') => "set",
+                    Some('+') => "up",
+                    Some('-') => "down",
+                    _ => return Err(format!("unexpected argument `{arg}`")),
+                };
+                let value = match &arg[1..] {
+                    "" => next_value(&mut args, arg)?,
+                    value => value,
+                };
+                // Anything else starting with `-` is an option we don't know,
+                // rather than a negative amount.
+                if value.parse::<f64>().is_err() {
+                    return Err(format!("unexpected argument `{arg}`"));
+                }
+                (verb, value)
+            }
+        };
+        cmd = vec![verb.into(), percent(value)?.into()];
+    }
+    flags.extend(cmd);
+    Ok(flags)
+}
+
+/// Translates a `light` command line, which is the flags `-G`, `-S N`, `-A N`,
+/// `-U N` and `-T FACTOR`, along with `-r` for raw values and `-L` to list
+/// devices. Like `light`, flags can be run together, like `-rG`.
+///
+/// `-s` picks a device, given as `sysfs/backlight/NAME`, or
+/// `sysfs/backlight/auto` for the usual one. `light`'s own saved minimum and
+/// saved brightness aren't supported.
+fn light(argv: &[OsString]) -> Result<Vec<OsString>, String> {
+    let mut flags = vec![argv[0].clone()];
+    // With no operation, light prints the brightness.
+    let mut cmd = vec!["get".to_owned(), "--bare".to_owned()];
+    let mut change = None;
+    let mut raw = false;
+    let mut args = argv[1..].iter();
+    while let Some(arg) = args.next() {
+        let arg = arg.to_str().ok_or("arguments must be UTF-8")?;
+        let Some(cluster) = arg.strip_prefix('-').filter(|c| !c.is_empty())
+        else {
+            return Err(format!("unexpected argument `{arg}`"));
+        };
+        for (i, flag) in cluster.char_indices() {
+            // Options with a value take the rest of the word, or the next one,
+            // and end the word either way.
+            let flag_name = format!("-{flag}");
+            let mut value = || match &cluster[i + 1..] {
+                "" => next_value(&mut args, &flag_name),
+                rest => Ok(rest),
+            };
+            match flag {
+                'G' => {
+                    cmd = vec!["get".to_owned(), "--bare".to_owned()];
+                    change = None;
+                }
+                'L' => {
+                    cmd = vec!["list".to_owned(), "--plain".to_owned()];
+                    change = None;
+                }
+                'r' => raw = true,
+                'S' | 'A' | 'U' | 'T' => {
+                    change = Some((flag, value()?));
+                    break;
+                }
+                's' => {
+                    let path = value()?;
+                    let Some(name) = path.strip_prefix("sysfs/backlight/")
+                    else {
+                        return Err(format!(
+                            "only backlight devices are supported, not \
+                             `{path}`"
+                        ));
+                    };
+                    if name != "auto" {
+                        flags.push("--name".into());
+                        flags.push(name.into());
+                    }
+                    break;
+                }
+                'v' => {
+                    value()?;
+                    break;
+                }
+                _ => return Err(format!("`-{flag}` isn't supported")),
+            }
+        }
+    }
+
+    if raw {
+        flags.push("--raw".into());
+    }
+    if let Some((flag, value)) = change {
+        cmd = match flag {
+            'T' => match value.parse::<f64>() {
+                Ok(factor) if factor >= 1. => {
+                    vec!["up".to_owned(), format!("{factor}x")]
+                }
+                Ok(factor) if factor > 0. => {
+                    vec!["down".to_owned(), format!("{}x", 1. / factor)]
+                }
+                _ => return Err(format!("bad factor `{value}`")),
+            },
+            _ => {
+                let verb = match flag {
+                    'S' => "set",
+                    'A' => "up",
+                    _ => "down",
+                };
+                let value = if raw {
+                    match value.parse::<f64>() {
+                        Ok(n) if n >= 0. => format!("{}r", n.round()),
+                        _ => return Err(format!("bad value `{value}`")),
+                    }
+                } else {
+                    percent(value)?
+                };
+                vec![verb.to_owned(), value]
+            }
+        };
+    }
+    flags.extend(cmd.into_iter().map(OsString::from));
+    Ok(flags)
+}
+
+/// Takes the value following option `flag` from `args`.
+fn next_value<'a>(
+    args: &mut impl Iterator<Item = &'a OsString>,
+    flag: &str,
+) -> Result<&'a str, String> {
+    args.next()
+        .and_then(|arg| arg.to_str())
+        .ok_or_else(|| format!("`{flag}` needs a value"))
+}
+
+/// Converts a percentage, which may have a fraction, into a `brightr` value.
+/// Like the other tools, we saturate rather than rejecting values over 100%.
+fn percent(text: &str) -> Result<String, String> {
+    match text.parse::<f64>() {
+        Ok(pct) if pct >= 0. => Ok(format!("{}%", pct.min(100.).round())),
+        _ => Err(format!("bad percentage `{text}`")),
+    }
+}
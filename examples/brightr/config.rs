@@ -0,0 +1,791 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Configuration file support for the command line tool.
+//!
+//! The config file is entirely optional. If it's missing, we behave exactly as
+//! if it were present and empty.
+
+use crate::{
+    backend::{Backend, DEFAULT_CHAIN},
+    thermal, Unit, Value,
+};
+use anyhow::{bail, Context};
+use serde::{de, Deserialize, Deserializer};
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use toml::Spanned;
+
+/// Contents of the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// User-defined command aliases, mapping a new verb to the (whitespace
+    /// separated) arguments it should expand to.
+    pub alias: BTreeMap<String, String>,
+
+    /// Names of the user's choosing for devices, mapping each to the device's
+    /// real name. These can be used anywhere a device name can.
+    pub devices: BTreeMap<String, String>,
+
+    /// Device to use when none is named on the command line, instead of
+    /// finding one automatically.
+    pub device: Option<String>,
+
+    /// Exponent to use when none is given on the command line.
+    #[serde(deserialize_with = "positive")]
+    pub exponent: Option<f64>,
+
+    /// Floor to use when none is given on the command line, in raw units.
+    pub min: Option<u32>,
+
+    /// Settings that apply only on particular machines, by host name. The
+    /// section for this machine is applied over the rest when loading; they
+    /// are all kept, but only so that `problems` can say where a setting came
+    /// from.
+    pub host: BTreeMap<String, toml::Table>,
+
+    /// Highest brightness we'll ever set, unless overridden on the command
+    /// line. This is given as a string like the command line values, but a
+    /// bare number is taken to be a percentage.
+    #[serde(deserialize_with = "parse_optional_cap")]
+    pub cap: Option<Value>,
+
+    /// An additional cap that applies only when running on a low battery.
+    pub low_battery: Option<LowBattery>,
+
+    /// The files this was loaded from, earliest first, so that `problems` can
+    /// say where each setting is.
+    #[serde(skip)]
+    sources: Vec<Source>,
+
+    /// An additional cap that applies only when the system is running hot.
+    pub thermal: Option<Thermal>,
+
+    /// A custom policy written in Rhai, which can return a cap. This is only
+    /// supported if built with the `scripting` feature.
+    pub script: Option<String>,
+
+    /// Ways to change the brightness, in order of preference. If one fails,
+    /// we try the next.
+    #[serde(deserialize_with = "nonempty")]
+    pub backends: Option<Vec<Backend>>,
+
+    /// For devices that can only be on or off, the percentage at or above
+    /// which `set` turns them on. Defaults to 50.
+    #[serde(deserialize_with = "optional_percentage")]
+    pub binary_threshold: Option<u32>,
+
+    /// How the keyboard backlight should follow the display.
+    pub keyboard: Keyboard,
+
+    /// For devices whose hardware has fewer levels than their raw range
+    /// suggests, the size of a real step in raw units, by device name.
+    #[serde(deserialize_with = "step_sizes")]
+    pub steps: BTreeMap<String, u32>,
+
+    /// Fade every change made by `set`, `up`, `down` and `adjust` over this
+    /// many milliseconds, as if `--smooth` were given. Written as `true` for
+    /// the usual length, or a number of milliseconds.
+    #[serde(deserialize_with = "fade_length")]
+    pub smooth: Option<u64>,
+
+    /// Brightness for `apply-boot` to set, written like `cap`.
+    #[serde(deserialize_with = "parse_optional_brightness")]
+    pub boot: Option<Value>,
+}
+
+/// Settings for keeping the keyboard backlight in step with the display.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Keyboard {
+    /// Turn the keyboard backlight off when we turn the display all the way
+    /// down, and back on when we turn the display back up.
+    pub off_with_display: bool,
+
+    /// Set the keyboard backlight to this fraction of the display's
+    /// brightness whenever we change the display. This takes precedence over
+    /// `off_with_display`.
+    #[serde(deserialize_with = "fraction")]
+    pub follow: Option<f64>,
+}
+
+/// Settings for capping the brightness when the battery is low.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LowBattery {
+    /// Battery charge percentage below which the cap applies.
+    #[serde(deserialize_with = "percentage")]
+    pub below: f64,
+    /// Highest brightness we'll set while the battery is low, written the same
+    /// way as the top-level `cap`.
+    #[serde(deserialize_with = "parse_cap")]
+    pub cap: Value,
+    /// If set, the cap falls further as the battery drains: from `cap` at
+    /// `below` percent, in a straight line, to this at empty. It's in the
+    /// same units as `cap`.
+    #[serde(default, deserialize_with = "parse_optional_cap")]
+    pub floor: Option<Value>,
+}
+
+impl LowBattery {
+    /// Works out the cap with `percentage` charge left, which should be
+    /// below `below`: `cap`, or, with a `floor`, somewhere between the two.
+    pub fn cap_at(&self, percentage: f64) -> anyhow::Result<Value> {
+        let floor = self.check_floor()?.unwrap_or(self.cap);
+        let left = (percentage / self.below).clamp(0., 1.);
+        let (top, bottom) =
+            (f64::from(self.cap.amount), f64::from(floor.amount));
+        Ok(Value {
+            amount: (bottom + (top - bottom) * left).round() as u32,
+            unit: self.cap.unit,
+        })
+    }
+
+    /// Checks that `floor`, if set, can be reached from `cap`: it has to be
+    /// in the same units, and no higher.
+    fn check_floor(&self) -> anyhow::Result<Option<Value>> {
+        let Some(floor) = self.floor else {
+            return Ok(None);
+        };
+        // Without a unit, both are percentages.
+        let unit = |value: Value| value.unit.unwrap_or(Unit::Percent);
+        if unit(floor) != unit(self.cap) {
+            bail!("floor {floor} isn't in the same units as cap {}", self.cap);
+        }
+        if floor.amount > self.cap.amount {
+            bail!("floor {floor} is above cap {}", self.cap);
+        }
+        Ok(Some(floor))
+    }
+}
+
+impl Config {
+    /// Returns the backends to try, in order.
+    pub fn backends(&self) -> &[Backend] {
+        self.backends.as_deref().unwrap_or(DEFAULT_CHAIN)
+    }
+
+    /// Loads the system-wide config file (see `system_path`), and then the
+    /// user's config file from its default location, or the location given
+    /// in the `BRIGHTR_CONFIG` environment variable. Settings in the user's
+    /// file win over the system-wide ones.
+    ///
+    /// Missing config files are not an error. Without either, this produces
+    /// the default config.
+    pub fn load() -> anyhow::Result<Self> {
+        Self::load_files([Some(system_path()), path()].into_iter().flatten())
+    }
+
+    /// Loads only the system-wide config file, as `brightr` sees it when
+    /// running early in boot, before the user's files may be available.
+    pub fn load_system() -> anyhow::Result<Self> {
+        Self::load_files([system_path()])
+    }
+
+    /// Loads and merges the config files at `paths`, later ones winning.
+    fn load_files(
+        paths: impl IntoIterator<Item = PathBuf>,
+    ) -> anyhow::Result<Self> {
+        let mut merged = toml::Table::new();
+        let mut sources = vec![];
+        for path in paths {
+            if let Some((table, source)) = read_table(&path)? {
+                merge(&mut merged, table);
+                sources.push(source);
+            }
+        }
+        let here = match (merged.get("host"), hostname()) {
+            (Some(toml::Value::Table(hosts)), Some(name)) => {
+                hosts.get(&name).cloned()
+            }
+            _ => None,
+        };
+        if let Some(toml::Value::Table(here)) = here {
+            merge(&mut merged, here);
+        }
+        let mut config: Self = toml::Value::Table(merged)
+            .try_into()
+            .context("bad combination of config files")?;
+        config.sources = sources;
+        Ok(config)
+    }
+
+    /// Finds where setting `key`, given as the path of keys leading to it,
+    /// was made, as `file:line:column`. This machine's `[host]` section wins,
+    /// as it does when loading, and then the last file that has it.
+    fn locate(&self, key: &[&str]) -> Option<String> {
+        let host = hostname();
+        let mut keys = vec![];
+        if let Some(host) = &host {
+            keys.push([&["host", host.as_str()], key].concat());
+        }
+        keys.push(key.to_vec());
+        for key in &keys {
+            if let Some(place) =
+                self.sources.iter().rev().find_map(|s| s.find(key))
+            {
+                return Some(place);
+            }
+        }
+        None
+    }
+
+    /// Returns the real name of the device called `name`, which may be one of
+    /// the names from the `[devices]` section.
+    pub fn device_name<'a>(&'a self, name: &'a str) -> &'a str {
+        self.devices.get(name).map_or(name, String::as_str)
+    }
+
+    /// Looks for mistakes that can't be caught while parsing, because they
+    /// depend on the rest of the program or on this machine. `cmd` is the
+    /// command line parser, used to check aliases against built-in commands.
+    ///
+    /// Returns a description of each problem found.
+    /// Each starts with where the setting is, if that's known.
+    pub fn problems(&self, cmd: &clap::Command) -> Vec<String> {
+        let mut problems = vec![];
+        let mut problem = |key: &[&str], message: String| {
+            problems.push(match self.locate(key) {
+                Some(place) => format!("{place}: {message}"),
+                None => message,
+            });
+        };
+        for (name, expansion) in &self.alias {
+            let key = ["alias", name.as_str()];
+            if cmd.find_subcommand(name).is_some() {
+                problem(
+                    &key,
+                    format!(
+                        "alias `{name}` has the same name as a built-in \
+                         command, so it will never be used"
+                    ),
+                );
+            }
+            if expansion.trim().is_empty() {
+                problem(&key, format!("alias `{name}` is empty"));
+            }
+        }
+        let present = crate::device_names();
+        let missing = |device: &str| {
+            let hint = crate::closest_name(device, &present)
+                .map(|close| format!("; did you mean `{close}`?"))
+                .unwrap_or_default();
+            format!("`{device}`, which isn't on this machine{hint}")
+        };
+        for (name, device) in &self.devices {
+            if !present.contains(device) {
+                let device = missing(device);
+                problem(
+                    &["devices", name],
+                    format!("device name `{name}` refers to {device}"),
+                );
+            }
+        }
+        // Devices can also be given by their connector, like `eDP-1`.
+        #[cfg(not(target_os = "openbsd"))]
+        let exists = |name: &str| {
+            present.iter().any(|p| p == name)
+                || crate::find_by_connector(name).is_some()
+        };
+        #[cfg(target_os = "openbsd")]
+        let exists = |name: &str| present.iter().any(|p| p == name);
+        // A name from `[devices]` has been checked already.
+        if let Some(device) = self
+            .device
+            .as_ref()
+            .filter(|d| !self.devices.contains_key(*d))
+        {
+            if !exists(device) {
+                // Sections for other machines name their own devices, which
+                // can't be checked from here, but this machine's is merged
+                // in, so say if the device came from there.
+                let here = hostname().filter(|host| {
+                    self.host
+                        .get(host)
+                        .is_some_and(|s| s.contains_key("device"))
+                });
+                let setting = match here {
+                    Some(host) => format!("[host.\"{host}\"] device"),
+                    None => "device".to_string(),
+                };
+                problem(
+                    &["device"],
+                    format!("{setting} is {}", missing(device)),
+                );
+            }
+        }
+        for device in self.steps.keys() {
+            if !present.contains(device) {
+                problem(
+                    &["steps", device],
+                    format!("[steps] has {}", missing(device)),
+                );
+            }
+        }
+        if let Some(zone) = self.thermal.as_ref().and_then(|t| t.zone.as_ref())
+        {
+            match thermal::zone_types() {
+                Ok(types) if !types.contains(zone) => problem(
+                    &["thermal", "zone"],
+                    format!("no thermal zone of type `{zone}` on this machine"),
+                ),
+                Ok(_) => (),
+                Err(e) => problem(
+                    &["thermal", "zone"],
+                    format!("can't list thermal zones: {e}"),
+                ),
+            }
+        }
+        if let Some(low) = &self.low_battery {
+            if let Err(e) = low.check_floor() {
+                problem(
+                    &["low_battery", "floor"],
+                    format!("[low_battery] {e}"),
+                );
+            }
+        }
+        if self.script.is_some() && !cfg!(feature = "scripting") {
+            problem(
+                &["script"],
+                "`script` is set, but brightr was built without the \
+                 `scripting` feature"
+                    .to_string(),
+            );
+        }
+        problems
+    }
+}
+
+/// Settings for capping the brightness when the system is hot.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Thermal {
+    /// Temperature, in degrees Celsius, above which the cap applies.
+    pub above: f64,
+    /// Type of thermal zone to check (e.g. `x86_pkg_temp`). If omitted, we use
+    /// the hottest zone in the system.
+    pub zone: Option<String>,
+    /// Highest brightness we'll set while running hot, written the same way as
+    /// the top-level `cap`.
+    #[serde(deserialize_with = "parse_cap")]
+    pub cap: Value,
+}
+
+/// Default config file written by `init-config`. Everything is commented out,
+/// so it behaves exactly like having no config at all.
+pub const TEMPLATE: &str = r#"# Configuration for brightr.
+#
+# Everything here is optional; lines starting with `#` are comments. Values
+# written like "80%" can also be given in the driver's raw units, like "1500r".
+# A bare number means percent.
+
+# Never set the brightness above this level, unless --override-cap is passed.
+#cap = "80%"
+
+# Ways to change the brightness, tried in order until one works. "logind"
+# (the default) works everywhere; "gnome" goes through GNOME's settings daemon
+# so its on-screen display and slider stay in sync; "sysfs" writes to the
+# device directly, which needs root or a udev rule.
+#backends = ["logind"]
+
+# Some devices can only be switched on or off. For these, `up` and `down` turn
+# them on and off, and `set` turns them on at or above this percentage.
+#binary_threshold = 50
+
+# A custom cap policy written in Rhai (requires the `scripting` feature). It
+# can use `on_battery`, `battery`, `temperature`, `device`, and `current`,
+# `target` and `max` in raw units, and returns a cap or ().
+#script = '''
+#if on_battery && battery < 50 { "40%" } else { () }
+#'''
+
+# Device to use when --name isn't given, and the --exponent and --min to use
+# when they aren't given.
+#device = "intel_backlight"
+#exponent = 3
+#min = 1
+
+# Brightness for `brightr apply-boot` to set early in boot, before anyone logs
+# in. `brightr apply-boot --print-unit` prints a systemd unit that runs it.
+#boot = "40%"
+
+# Fade every change, as if --smooth were given, unless --instant is passed.
+# Either true, for a quarter of a second, or a number of milliseconds.
+#smooth = true
+
+# Define your own commands. Each alias expands to the arguments given, so
+# `brightr dim` below is the same as `brightr -m 1 -e 4 down 10`.
+[alias]
+#dim = "-m 1 -e 4 down 10"
+#brighten = "-m 1 -e 4 up 10"
+
+# Give devices names of your own, to use with --name instead of the real ones.
+[devices]
+#laptop = "intel_backlight"
+#monitor = "ddcci7"
+
+# Turn the keyboard backlight (through UPower) off when the display is turned
+# all the way down, and restore it when the display comes back up.
+[keyboard]
+#off_with_display = true
+
+# Alternatively, keep the keyboard backlight at this fraction of the display's
+# brightness, updated whenever brightr changes the display.
+#follow = 0.5
+
+# Some panels have far fewer real levels than their raw range suggests. If
+# `brightr measure-steps` finds one, record its step size here, so that `up`
+# and `down` always move by at least one visible level.
+[steps]
+#intel_backlight = 4096
+
+# Apply a lower cap when running on a battery with less than `below` percent
+# charge remaining.
+#[low_battery]
+#below = 20
+#cap = "40%"
+# Optionally, lower the cap further as the battery drains, reaching this when
+# it's empty.
+#floor = "10%"
+
+# Apply a lower cap when the system is hotter than `above` degrees Celsius.
+# If `zone` is set, only thermal zones of that type are checked.
+#[thermal]
+#above = 85
+#zone = "x86_pkg_temp"
+#cap = "60%"
+
+# Settings for one machine only, by host name, which override the rest. This
+# lets one config file serve machines with different panels.
+#[host."work-laptop"]
+#device = "amdgpu_bl0"
+#exponent = 2
+"#;
+
+/// Deserializes a value from a string using its `FromStr` impl, so that config
+/// values are written the same way as on the command line.
+fn parse_str<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let text = String::deserialize(deserializer)?;
+    text.parse().map_err(de::Error::custom)
+}
+
+/// Deserializes a brightness cap, which is written like a command line value
+/// but can't be more than 100%.
+fn parse_cap<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Value, D::Error> {
+    let cap: Value = parse_str(deserializer)?;
+    if cap.unit != Some(Unit::Raw) && cap.amount > 100 {
+        return Err(de::Error::custom("cap can't be more than 100%"));
+    }
+    Ok(cap)
+}
+
+/// Deserializes an optional brightness, written like a command line value but
+/// no more than 100%.
+fn parse_optional_brightness<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Value>, D::Error> {
+    let value: Value = parse_str(deserializer)?;
+    if value.unit != Some(Unit::Raw) && value.amount > 100 {
+        return Err(de::Error::custom("brightness can't be more than 100%"));
+    }
+    Ok(Some(value))
+}
+
+/// Like `parse_cap`, for optional caps.
+fn parse_optional_cap<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Value>, D::Error> {
+    parse_cap(deserializer).map(Some)
+}
+
+/// Deserializes an optional list that, if present, can't be empty.
+fn nonempty<'de, D, T>(deserializer: D) -> Result<Option<Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    let list = Vec::deserialize(deserializer)?;
+    if list.is_empty() {
+        return Err(de::Error::custom("must list at least one"));
+    }
+    Ok(Some(list))
+}
+
+/// Deserializes an optional whole number that must be a percentage.
+fn optional_percentage<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u32>, D::Error> {
+    let pct = u32::deserialize(deserializer)?;
+    if pct > 100 {
+        return Err(de::Error::custom("must be between 0 and 100"));
+    }
+    Ok(Some(pct))
+}
+
+/// Deserializes an optional number that must be between 0 and 1.
+fn fraction<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<f64>, D::Error> {
+    let frac = f64::deserialize(deserializer)?;
+    if !(0. ..=1.).contains(&frac) {
+        return Err(de::Error::custom("must be between 0 and 1"));
+    }
+    Ok(Some(frac))
+}
+
+/// Deserializes an optional number that must be more than 0.
+fn positive<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<f64>, D::Error> {
+    let n = f64::deserialize(deserializer)?;
+    if n <= 0. {
+        return Err(de::Error::custom("must be more than 0"));
+    }
+    Ok(Some(n))
+}
+
+/// Deserializes `[steps]`, where every step must move at least one raw unit.
+fn step_sizes<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<String, u32>, D::Error> {
+    let steps = BTreeMap::<String, u32>::deserialize(deserializer)?;
+    if let Some(device) = steps.iter().find_map(|(d, &s)| (s == 0).then_some(d))
+    {
+        return Err(de::Error::custom(format!(
+            "step for `{device}` must be more than 0"
+        )));
+    }
+    Ok(steps)
+}
+
+/// Deserializes `smooth`, which is a bool or a number of milliseconds, into
+/// the length of fades, if there should be any.
+fn fade_length<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Smooth {
+        On(bool),
+        Millis(u64),
+    }
+    match Smooth::deserialize(deserializer) {
+        // The same length as `--smooth` without a number.
+        Ok(Smooth::On(on)) => Ok(on.then_some(250)),
+        Ok(Smooth::Millis(ms)) => Ok(Some(ms)),
+        Err(_) => Err(de::Error::custom(
+            "must be true, false or a number of milliseconds",
+        )),
+    }
+}
+
+/// Deserializes a number that must be a percentage.
+fn percentage<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<f64, D::Error> {
+    let pct = f64::deserialize(deserializer)?;
+    if !(0. ..=100.).contains(&pct) {
+        return Err(de::Error::custom("must be between 0 and 100"));
+    }
+    Ok(pct)
+}
+
+/// Reads the config file at `path`, checking that it makes sense on its own,
+/// and keeping track of where everything in it is. Returns `None` if there's
+/// no such file.
+fn read_table(path: &Path) -> anyhow::Result<Option<(toml::Table, Source)>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(e).with_context(|| {
+                format!("can't read config file {}", path.display())
+            });
+        }
+    };
+    // Checking each file as it's read means mistakes are reported against
+    // the file they're in, rather than the merged result.
+    let check = || -> anyhow::Result<toml::Table> {
+        let config = toml::from_str::<Config>(&text)?;
+        for (name, settings) in config.host {
+            let settings = toml::Value::Table(settings).try_into::<Config>();
+            match settings {
+                Ok(settings) if settings.host.is_empty() => (),
+                Ok(_) => bail!("[host.\"{name}\"] can't contain more hosts"),
+                Err(e) => bail!("in [host.\"{name}\"]: {e}"),
+            }
+        }
+        Ok(toml::from_str(&text)?)
+    };
+    let table = check()
+        .with_context(|| format!("bad config file {}", path.display()))?;
+    // Knowing where things are is only nice to have, so this doesn't fail the
+    // load, though nothing that passed the check should upset it.
+    let keys = toml::from_str(&text).unwrap_or_default();
+    let source = Source {
+        path: path.to_owned(),
+        text,
+        keys,
+    };
+    Ok(Some((table, source)))
+}
+
+/// A config file that was loaded, kept so that problems found later can be
+/// reported against the line they're on.
+#[derive(Debug)]
+struct Source {
+    path: PathBuf,
+    text: String,
+    keys: Keys,
+}
+
+impl Source {
+    /// Finds where `key`, the path of keys leading to a setting, is in the
+    /// file, as `file:line:column`.
+    fn find(&self, key: &[&str]) -> Option<String> {
+        let mut keys = &self.keys;
+        let mut start = None;
+        for k in key {
+            let (span, inner) = keys.0.get(*k)?;
+            start = Some(span.start);
+            keys = inner;
+        }
+        let before = &self.text[..start?];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+        Some(format!("{}:{line}:{column}", self.path.display()))
+    }
+}
+
+/// Where each key in a TOML document is, by byte offset, along with the keys
+/// inside it, if it's a table.
+#[derive(Debug, Default)]
+struct Keys(BTreeMap<String, (Range<usize>, Keys)>);
+
+impl<'de> Deserialize<'de> for Keys {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        /// Takes any value, recording the keys in tables.
+        struct KeysVisitor;
+
+        impl<'de> de::Visitor<'de> for KeysVisitor {
+            type Value = Keys;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("any TOML value")
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<Keys, A::Error> {
+                let mut keys = BTreeMap::new();
+                while let Some(key) = map.next_key::<Spanned<String>>()? {
+                    let span = key.span();
+                    let inner = map.next_value()?;
+                    keys.insert(key.into_inner(), (span, inner));
+                }
+                Ok(Keys(keys))
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<Keys, A::Error> {
+                while seq.next_element::<de::IgnoredAny>()?.is_some() {}
+                Ok(Keys::default())
+            }
+
+            fn visit_bool<E: de::Error>(self, _: bool) -> Result<Keys, E> {
+                Ok(Keys::default())
+            }
+
+            fn visit_i64<E: de::Error>(self, _: i64) -> Result<Keys, E> {
+                Ok(Keys::default())
+            }
+
+            fn visit_u64<E: de::Error>(self, _: u64) -> Result<Keys, E> {
+                Ok(Keys::default())
+            }
+
+            fn visit_f64<E: de::Error>(self, _: f64) -> Result<Keys, E> {
+                Ok(Keys::default())
+            }
+
+            fn visit_str<E: de::Error>(self, _: &str) -> Result<Keys, E> {
+                Ok(Keys::default())
+            }
+        }
+
+        deserializer.deserialize_any(KeysVisitor)
+    }
+}
+
+/// Merges config `over` into config `under`, with settings from `over`
+/// winning. Sections are merged setting by setting, so that one file can
+/// override part of a section from another.
+fn merge(under: &mut toml::Table, over: toml::Table) {
+    for (key, value) in over {
+        match (under.get_mut(&key), value) {
+            (Some(toml::Value::Table(under)), toml::Value::Table(over)) => {
+                merge(under, over)
+            }
+            (Some(slot), value) => *slot = value,
+            (None, value) => {
+                under.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Finds out this machine's host name, for picking a `[host]` section.
+fn hostname() -> Option<String> {
+    // Linux has the first; OpenBSD, the second. The last is a fallback for
+    // Linux systems without procfs, like some containers.
+    ["/proc/sys/kernel/hostname", "/etc/myname", "/etc/hostname"]
+        .iter()
+        .find_map(|path| fs::read_to_string(path).ok())
+        .map(|name| name.trim().to_owned())
+        .filter(|name| !name.is_empty())
+}
+
+/// Works out where the system-wide config file lives. This is normally
+/// `/etc/brightr/config.toml`, but can be changed with the
+/// `BRIGHTR_SYSTEM_CONFIG` environment variable.
+pub fn system_path() -> PathBuf {
+    match std::env::var_os("BRIGHTR_SYSTEM_CONFIG") {
+        Some(path) => path.into(),
+        None => PathBuf::from("/etc/brightr/config.toml"),
+    }
+}
+
+/// Works out where the config file should live. This follows the XDG base
+/// directory conventions, since that's what most Linux desktops expect.
+///
+/// Returns `None` if we can't work out a location at all, which basically
+/// means `HOME` isn't set.
+pub fn path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("BRIGHTR_CONFIG") {
+        return Some(path.into());
+    }
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("brightr").join("config.toml"))
+}
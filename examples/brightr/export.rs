@@ -0,0 +1,218 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The `export` and `import` commands, which save the state of every device
+//! to a file and apply it again later, possibly on another machine.
+
+use crate::{backend::Writer, config::Config, policy, Brightr, Unit, Value};
+use anyhow::{bail, Context};
+use brightr::Backlight;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+/// Formats that `export` can write.
+#[derive(Copy, Clone, Debug, clap::ValueEnum)]
+pub enum Format {
+    /// TOML, like the config file.
+    Toml,
+    /// JSON.
+    Json,
+}
+
+/// Everything `export` saves.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct State {
+    /// The `--exponent` in use when the state was saved.
+    pub exponent: f64,
+    /// The `--min` in use when the state was saved.
+    pub min: u32,
+    /// The cap from the config file, if any, written like a command line
+    /// value.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cap: Option<String>,
+    /// The setting of each device, by name.
+    pub devices: BTreeMap<String, Device>,
+}
+
+/// The saved setting of one device.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Device {
+    /// Raw brightness.
+    pub brightness: u32,
+    /// The device's raw maximum, so that the setting can be scaled to a
+    /// device with a different range.
+    pub max: u32,
+}
+
+impl State {
+    /// Reads the current state of the devices in `names`, along with the
+    /// curve settings from `args` and the cap from `config`. Devices that
+    /// can't be read are reported and left out.
+    pub fn capture(args: &Brightr, config: &Config, names: &[String]) -> Self {
+        let mut devices = BTreeMap::new();
+        for name in names {
+            match brightr::use_specific_backlight(name.as_str()) {
+                Ok((bl, brightness)) => {
+                    devices.insert(
+                        bl.name,
+                        Device {
+                            brightness,
+                            max: bl.max,
+                        },
+                    );
+                }
+                Err(e) => eprintln!("can't read {name}: {e}"),
+            }
+        }
+        State {
+            exponent: args.exponent,
+            min: args.min,
+            cap: config.cap.map(|cap| cap.to_string()),
+            devices,
+        }
+    }
+
+    /// Parses a saved state, which may be in either format.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        // A TOML file can't start with a brace, so this is unambiguous.
+        if text.trim_start().starts_with('{') {
+            Ok(serde_json::from_str(text)?)
+        } else {
+            Ok(toml::from_str(text)?)
+        }
+    }
+
+    /// Sets each saved device back to its saved setting, within the floor
+    /// from `args` and any cap from `config`'s policies. If `args` names a
+    /// device, only that one is restored.
+    ///
+    /// Devices that are missing or can't be set are reported. Normally the
+    /// rest are still restored, but if `atomic` is set, a missing device means
+    /// nothing is changed, and a failed change puts back the devices already
+    /// changed.
+    pub fn restore(
+        &self,
+        args: &Brightr,
+        config: &Config,
+        atomic: bool,
+    ) -> anyhow::Result<()> {
+        let policies = if args.override_cap {
+            vec![]
+        } else {
+            policy::from_config(config)
+        };
+
+        // Work out every change before making any, so that a missing device
+        // can stop us before anything has changed.
+        let mut changes = vec![];
+        let mut failed = 0;
+        for (name, saved) in &self.devices {
+            if args.name.as_ref().is_some_and(|wanted| wanted != name) {
+                continue;
+            }
+            let (bl, previous) =
+                match brightr::use_specific_backlight(name.as_str()) {
+                    Ok(found) => found,
+                    Err(e) => {
+                        eprintln!("can't restore {name}: {e}");
+                        failed += 1;
+                        continue;
+                    }
+                };
+            let requested = saved.scaled_to(&bl);
+            let context = policy::Context {
+                backlight: &bl,
+                current: previous,
+                target: Some(requested),
+            };
+            let verdicts = policy::evaluate(&policies, &context);
+            for verdict in &verdicts {
+                if let Err(e) = &verdict.cap {
+                    eprintln!(
+                        "can't apply {} policy to {name}: {e}",
+                        verdict.policy
+                    );
+                }
+            }
+            let (ceiling, _) =
+                policy::ceiling(&verdicts, bl.max, |cap: Value| {
+                    cap.to_raw(&bl, args.exponent, Unit::Percent)
+                });
+            let floor = args.min.min(ceiling);
+            let target = requested.clamp(floor, ceiling);
+            changes.push((bl, previous, target));
+        }
+        if atomic && failed != 0 {
+            bail!("couldn't restore {failed} device(s), so changed nothing");
+        }
+
+        let chain = args.backends(config);
+        let mut writer = Writer::new(&chain);
+        let mut done = vec![];
+        for (bl, previous, target) in &changes {
+            match writer.set(bl, *target) {
+                Ok(_) => done.push((bl, *previous)),
+                Err(e) if atomic => {
+                    // Undo in the reverse of the order we applied them.
+                    for (bl, previous) in done.iter().rev() {
+                        if let Err(e) = writer.set(bl, *previous) {
+                            eprintln!("can't roll back {}: {e}", bl.name);
+                        }
+                    }
+                    return Err(anyhow::Error::new(e).context(format!(
+                        "can't restore {}, so rolled back {} other device(s)",
+                        bl.name,
+                        done.len()
+                    )));
+                }
+                Err(e) => {
+                    eprintln!("can't restore {}: {e}", bl.name);
+                    failed += 1;
+                }
+            }
+        }
+        if failed != 0 {
+            bail!("couldn't restore {failed} device(s)");
+        }
+        Ok(())
+    }
+
+    /// Writes this state to stdout in `format`.
+    pub fn print(&self, format: Format) -> anyhow::Result<()> {
+        match format {
+            Format::Toml => print!("{}", toml::to_string(self)?),
+            Format::Json => println!("{}", serde_json::to_string_pretty(self)?),
+        }
+        Ok(())
+    }
+}
+
+impl Device {
+    /// Works out the equivalent of this setting on `bl`. Devices with the
+    /// same range get exactly the saved value; others get the same fraction
+    /// of their range, which is also the same percentage whatever the
+    /// exponent.
+    fn scaled_to(&self, bl: &Backlight) -> u32 {
+        if self.max == bl.max || self.max == 0 {
+            return self.brightness.min(bl.max);
+        }
+        let fraction =
+            f64::from(self.brightness.min(self.max)) / f64::from(self.max);
+        (fraction * f64::from(bl.max)).round() as u32
+    }
+}
+
+/// Reads a saved state from `path`, or from stdin if `path` is `-`.
+pub fn read(path: &Path) -> anyhow::Result<State> {
+    let text = if path == Path::new("-") {
+        io::read_to_string(io::stdin()).context("can't read stdin")?
+    } else {
+        fs::read_to_string(path)
+            .with_context(|| format!("can't read {}", path.display()))?
+    };
+    State::parse(&text)
+        .with_context(|| format!("bad saved state in {}", path.display()))
+}
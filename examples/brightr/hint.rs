@@ -0,0 +1,257 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Explanations for common failures.
+//!
+//! Most of the ways brightr fails come down to a handful of causes, like not
+//! running in the desktop session, or lacking permission to write to sysfs.
+//! The raw errors from DBus or the kernel rarely say so, so here we recognize
+//! them and suggest what to do next.
+
+use crate::backend;
+use brightr::Error;
+use std::{env, fs, io, path::Path};
+use zbus::DBusError;
+
+/// Words in a machine's DMI vendor or product name that give away a virtual
+/// machine.
+const VM_NAMES: &[&str] = &[
+    "QEMU",
+    "KVM",
+    "VMware",
+    "VirtualBox",
+    "innotek",
+    "Xen",
+    "Bochs",
+    "BHYVE",
+    "Parallels",
+    "Virtual Machine",
+];
+
+/// A failure with a known cause.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// There's no /sys/class/backlight at all.
+    NoSysfs,
+    /// There are no usable backlight devices.
+    NoDevices,
+    /// The device the user asked for doesn't exist.
+    NoSuchDevice,
+    /// We aren't allowed to write to the device in sysfs.
+    PermissionDenied,
+    /// logind refused to change the brightness for us.
+    LogindDenied,
+    /// We're not in a logind session with a seat.
+    NoSession,
+    /// A DBus service we need isn't running.
+    ServiceMissing,
+    /// We can't reach DBus at all.
+    NoDbus,
+    /// The config file couldn't be parsed.
+    BadConfig,
+    /// There are no devices because we're in a container that hasn't been
+    /// given the host's.
+    Container,
+    /// There are no devices because we're in a virtual machine.
+    VirtualMachine,
+    /// There are no devices here, and we're being run from another machine,
+    /// whose display is probably the one the user meant.
+    Remote,
+}
+
+impl Problem {
+    /// Finds the cause of `e`, if it's a failure we recognize.
+    pub fn of(e: &anyhow::Error) -> Option<Self> {
+        match e.chain().find_map(Self::of_one)? {
+            problem @ (Problem::NoSysfs | Problem::NoDevices) => {
+                Some(Self::environment().unwrap_or(problem))
+            }
+            problem => Some(problem),
+        }
+    }
+
+    /// Looks for signs that we're somewhere that has no backlight devices by
+    /// its nature, to explain finding none.
+    fn environment() -> Option<Self> {
+        let set = |name| env::var_os(name).is_some_and(|v| !v.is_empty());
+        // An X display with a host name is being forwarded, over SSH say.
+        let remote_display = env::var("DISPLAY").is_ok_and(|display| {
+            let host = display.split(':').next().unwrap_or_default();
+            !host.is_empty() && host != "unix" && !host.starts_with('/')
+        });
+        if set("SSH_CONNECTION") || remote_display {
+            return Some(Problem::Remote);
+        }
+        // systemd-nspawn and friends set `container`; Docker and Podman
+        // leave files behind instead.
+        if set("container")
+            || Path::new("/.dockerenv").exists()
+            || Path::new("/run/.containerenv").exists()
+        {
+            return Some(Problem::Container);
+        }
+        let dmi = brightr::sysfs_root().join("class/dmi/id");
+        let vm = ["sys_vendor", "product_name"].iter().any(|attr| {
+            fs::read_to_string(dmi.join(attr))
+                .is_ok_and(|text| VM_NAMES.iter().any(|vm| text.contains(vm)))
+        });
+        vm.then_some(Problem::VirtualMachine)
+    }
+
+    /// Checks whether this problem is down to where we're running, rather
+    /// than anything the user can fix by changing how they run us.
+    pub fn is_environment(self) -> bool {
+        matches!(
+            self,
+            Problem::Container | Problem::VirtualMachine | Problem::Remote
+        )
+    }
+
+    /// Says what's wrong more plainly than the underlying error, for problems
+    /// where that's misleading.
+    pub fn summary(self) -> Option<&'static str> {
+        Some(match self {
+            Problem::Container => {
+                "no backlight hardware is exposed to this container"
+            }
+            Problem::VirtualMachine => {
+                "no backlight hardware in this virtual machine"
+            }
+            Problem::Remote => "no backlight hardware on this remote machine",
+            _ => return None,
+        })
+    }
+
+    /// Like `of`, but for a single error in a chain.
+    fn of_one(e: &(dyn std::error::Error + 'static)) -> Option<Self> {
+        if let Some(failed) = e.downcast_ref::<backend::Failed>() {
+            // The first backend is the one the user most wants to work.
+            return failed.0.iter().find_map(|(_, e)| Self::of(e));
+        }
+        if let Some(e) = e.downcast_ref::<zbus::Error>() {
+            return Self::of_dbus(e);
+        }
+        if e.is::<toml::de::Error>() {
+            return Some(Problem::BadConfig);
+        }
+        match e.downcast_ref::<Error>()? {
+            Error::SysAccess(e) if e.kind() == io::ErrorKind::NotFound => {
+                Some(Problem::NoSysfs)
+            }
+            Error::EternalDarkness => Some(Problem::NoDevices),
+            e @ Error::Access(..) if e.is_no_device() => {
+                Some(Problem::NoSuchDevice)
+            }
+            e @ Error::Access(..) if e.is_permission_denied() => {
+                Some(Problem::PermissionDenied)
+            }
+            _ => None,
+        }
+    }
+
+    /// Recognizes problems talking to logind and friends.
+    fn of_dbus(e: &zbus::Error) -> Option<Self> {
+        let name = match e {
+            zbus::Error::MethodError(name, _, _) => name.to_string(),
+            zbus::Error::FDO(e) => e.name().to_string(),
+            zbus::Error::InputOutput(_) | zbus::Error::Address(_) => {
+                return Some(Problem::NoDbus)
+            }
+            _ => return None,
+        };
+        match name.as_str() {
+            "org.freedesktop.DBus.Error.AccessDenied"
+            | "org.freedesktop.DBus.Error.InteractiveAuthorizationRequired" => {
+                Some(Problem::LogindDenied)
+            }
+            "org.freedesktop.login1.NoSessionForPID"
+            | "org.freedesktop.DBus.Error.UnknownObject"
+            | "org.freedesktop.DBus.Error.NotSupported" => {
+                Some(Problem::NoSession)
+            }
+            "org.freedesktop.DBus.Error.ServiceUnknown"
+            | "org.freedesktop.DBus.Error.NameHasNoOwner" => {
+                Some(Problem::ServiceMissing)
+            }
+            _ => None,
+        }
+    }
+
+    /// Short, stable name for the problem, for scripts to match on.
+    pub fn code(self) -> &'static str {
+        match self {
+            Problem::NoSysfs => "no-sysfs",
+            Problem::NoDevices => "no-devices",
+            Problem::NoSuchDevice => "no-such-device",
+            Problem::PermissionDenied => "permission-denied",
+            Problem::LogindDenied => "logind-denied",
+            Problem::NoSession => "no-session",
+            Problem::ServiceMissing => "service-missing",
+            Problem::NoDbus => "no-dbus",
+            Problem::BadConfig => "bad-config",
+            Problem::Container => "container",
+            Problem::VirtualMachine => "virtual-machine",
+            Problem::Remote => "remote",
+        }
+    }
+
+    /// Suggests what the user might do about the problem.
+    pub fn hint(self) -> Option<&'static str> {
+        Some(match self {
+            Problem::NoSysfs => {
+                "there's no /sys/class/backlight, so either sysfs isn't \
+                 mounted or this isn't Linux."
+            }
+            Problem::NoDevices => {
+                "the kernel isn't offering any backlight devices in \
+                 /sys/class/backlight. Laptop panels usually appear there \
+                 once their graphics driver is loaded; external monitors \
+                 never do."
+            }
+            Problem::PermissionDenied => {
+                "writing to the device directly needs write access to its \
+                 files in sysfs: `brightness`, or `bl_power` for `on` and \
+                 `off`. Run as root, or install a udev rule giving your user \
+                 access (see the README)."
+            }
+            Problem::LogindDenied => {
+                "logind only lets the user logged in at the display's seat \
+                 change its brightness. Run brightr from that desktop \
+                 session, or try `--backend sysfs`."
+            }
+            Problem::NoSession => {
+                "brightr isn't running in a logind session with a seat (for \
+                 example, it's running over SSH or as a service), so logind \
+                 won't change the brightness for it. Run it from the desktop \
+                 session, or try `--backend sysfs`."
+            }
+            Problem::ServiceMissing => {
+                "a service brightr needs isn't running. The `gnome` backend \
+                 needs GNOME's settings daemon, and `--keyboard` needs \
+                 UPower."
+            }
+            Problem::NoDbus => {
+                "can't reach DBus. If this system doesn't run it, try \
+                 `--no-dbus`."
+            }
+            Problem::Container => {
+                "brightr seems to be running in a container, which can't see \
+                 the host's backlight devices. Run it on the host, or give the \
+                 container /sys/class/backlight and the devices it links to."
+            }
+            Problem::VirtualMachine => {
+                "this looks like a virtual machine, and virtual displays have \
+                 no backlight. Run brightr on the host instead."
+            }
+            Problem::Remote => {
+                "this is a remote session, so brightr is looking at the \
+                 machine it's running on, not the one in front of you. Run \
+                 it on the machine whose display you want to adjust."
+            }
+            // The error itself says what's wrong with a device name or the
+            // config file.
+            Problem::NoSuchDevice | Problem::BadConfig => return None,
+        })
+    }
+}
@@ -0,0 +1,69 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Keeping the keyboard backlight in step with the display.
+
+use crate::config;
+use anyhow::Context;
+use brightr::{upower::KbdBacklight, Backlight};
+use std::{fs, io, path::PathBuf};
+
+/// Adjusts the keyboard backlight, if `settings` ask for it, after display `bl`
+/// has been set to raw value `target`. `e` is the exponent in use for the
+/// display.
+pub fn display_changed(
+    settings: &config::Keyboard,
+    bl: &Backlight,
+    e: f64,
+    target: u32,
+) -> anyhow::Result<()> {
+    if settings.follow.is_none() && !settings.off_with_display {
+        return Ok(());
+    }
+    let kbd = KbdBacklight::connect()?;
+
+    if let Some(fraction) = settings.follow {
+        // Follow the display's perceived brightness, rather than its raw
+        // setting, which is what the user is actually looking at.
+        let display = bl.fraction_from_raw(target, e);
+        let max = kbd.max_brightness()?;
+        let level = (display * fraction * f64::from(max)).round() as u32;
+        kbd.set_brightness(level.min(max))?;
+        return Ok(());
+    }
+
+    if target == 0 {
+        // Remember where the keyboard was, so we can put it back, unless it's
+        // already off, in which case there's nothing to remember.
+        let level = kbd.brightness()?;
+        if level > 0 {
+            if let Some(path) = saved_level_path() {
+                fs::create_dir_all(path.parent().unwrap())?;
+                fs::write(&path, level.to_string()).with_context(|| {
+                    format!("can't save keyboard level to {}", path.display())
+                })?;
+            }
+            kbd.set_brightness(0)?;
+        }
+    } else if let Some(path) = saved_level_path() {
+        // Only restore the keyboard if we were the ones who turned it off.
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                fs::remove_file(&path)?;
+                kbd.set_brightness(text.trim().parse()?)?;
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+/// Where we keep the keyboard level while the display is dark. This is in the
+/// runtime directory, so it doesn't outlive the login session. Returns `None`
+/// if there's no runtime directory.
+fn saved_level_path() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_RUNTIME_DIR")?;
+    Some(PathBuf::from(dir).join("brightr").join("keyboard-level"))
+}
@@ -0,0 +1,76 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The `list` command, which shows every backlight device.
+
+use std::io::{self, IsTerminal};
+
+/// ANSI escapes used to highlight the active device.
+const HIGHLIGHT: &str = "\x1b[1;32m";
+/// ANSI escape that ends highlighting.
+const RESET: &str = "\x1b[0m";
+
+/// Runs the `list` command on the devices in `names`, using exponent `e` for
+/// percentages. `active` is the device other commands would use, if any.
+///
+/// Unless `plain` is set, this prints a header, and highlights the active
+/// device if stdout is a terminal and the user hasn't asked for no color.
+pub fn run(names: &[String], active: Option<&str>, e: f64, plain: bool) {
+    let rows = names
+        .iter()
+        .map(|name| {
+            let setting = match brightr::use_specific_backlight(name.as_str()) {
+                Ok((bl, current)) if bl.max == 1 => [
+                    format!("{current}/1"),
+                    (if current == 0 { "off" } else { "on" }).to_string(),
+                ],
+                Ok((bl, current)) => [
+                    format!("{current}/{}", bl.max),
+                    format!("{}%", bl.percent_from_raw(current, e)),
+                ],
+                Err(e) => [format!("unreadable: {e}"), String::new()],
+            };
+            (Some(name.as_str()) == active, name, setting)
+        })
+        .collect::<Vec<_>>();
+
+    let width = |column: usize, heading: &str| {
+        rows.iter()
+            .map(|(_, name, setting)| match column {
+                0 => name.len(),
+                _ => setting[column - 1].len(),
+            })
+            .chain([heading.len()])
+            .max()
+            .unwrap_or(0)
+    };
+    let widths = [width(0, "DEVICE"), width(1, "RAW")];
+
+    // https://no-color.org says any non-empty value means no color.
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    let color = !plain && !no_color && io::stdout().is_terminal();
+
+    if !plain {
+        println!(
+            "  {:w0$}  {:w1$}  PERCENT",
+            "DEVICE",
+            "RAW",
+            w0 = widths[0],
+            w1 = widths[1]
+        );
+    }
+    for (is_active, name, [raw, percent]) in &rows {
+        let line = format!(
+            "{} {name:w0$}  {raw:w1$}  {percent}",
+            if *is_active { '*' } else { ' ' },
+            w0 = widths[0],
+            w1 = widths[1],
+        );
+        if color && *is_active {
+            println!("{HIGHLIGHT}{}{RESET}", line.trim_end());
+        } else {
+            println!("{}", line.trim_end());
+        }
+    }
+}
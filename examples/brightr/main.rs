@@ -0,0 +1,1405 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! A simple program for adjusting display backlight settings on Linux.
+//!
+//! This program uses systemd and logind to set the backlight without requiring
+//! root privileges. It will only work when run by a user who is currently
+//! logged in at the seat that controls the display in question.
+
+mod backend;
+mod bench;
+mod boot;
+mod compat;
+mod config;
+mod export;
+mod hint;
+mod keyboard;
+mod list;
+mod measure_steps;
+mod policy;
+mod power;
+mod ramp_test;
+mod snapshot;
+mod status;
+mod stream;
+mod test_device;
+mod thermal;
+
+use anyhow::{bail, Context};
+use backend::Backend;
+use brightr::{
+    adjust::{AdjustOptions, Adjustment, Clamp},
+    fade::{self, Easing},
+    upower::KbdBacklight,
+    Backlight, Brightness, BrightnessFile, Subsystem,
+};
+use clap::{
+    error::ErrorKind, parser::ValueSource, CommandFactory, FromArgMatches,
+    Parser,
+};
+use config::Config;
+use hint::Problem;
+use log::{debug, info};
+use std::{
+    collections::BTreeSet,
+    ffi::OsString,
+    fmt, fs,
+    io::{self, IsTerminal, Write},
+    path::PathBuf,
+    process::ExitCode,
+    str::FromStr,
+    thread,
+    time::Duration,
+};
+
+/// Exit status from `get --exit-status` when the device is at its maximum.
+/// This steers clear of 1, for errors, and 2, which clap uses for bad usage.
+const AT_MAX: u8 = 3;
+/// Exit status from `get --exit-status` when the device is at the floor.
+const AT_FLOOR: u8 = 4;
+/// Exit status when we're somewhere that can't have backlight devices, like a
+/// container or virtual machine, rather than failing for an ordinary reason.
+const UNSUPPORTED: u8 = 5;
+
+/// Adjust display backlight.
+#[derive(Parser)]
+struct Brightr {
+    /// Name of backlight device to adjust, or of the display connector it
+    /// lights, like `eDP-1`. Use this to override the automatic detection
+    /// logic.
+    #[clap(short, long, global = true, help_heading = "Device Options")]
+    name: Option<String>,
+
+    /// Adjust the keyboard backlight, through UPower, instead of a display.
+    #[clap(
+        short,
+        long,
+        global = true,
+        conflicts_with = "name",
+        help_heading = "Device Options"
+    )]
+    keyboard: bool,
+
+    /// Change the brightness using only this backend (logind, gnome or sysfs),
+    /// instead of the ones listed in the config file.
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        hide_possible_values = true,
+        conflicts_with = "keyboard",
+        help_heading = "Device Options"
+    )]
+    backend: Option<Backend>,
+
+    /// Read the current brightness from `actual_brightness`, what the hardware
+    /// is really showing, rather than the last value written. This only makes
+    /// a difference for drivers where the two disagree.
+    #[clap(long, global = true, help_heading = "Device Options")]
+    actual: bool,
+
+    /// Use the driver's raw brightness values for all input and output instead
+    /// of percentages.
+    #[clap(short, long, global = true, help_heading = "Device Options")]
+    raw: bool,
+
+    /// Map percentages to raw values using this exponent, to apply gamma
+    /// correction. A value of 2-4 is often about right; the default of 1 makes
+    /// the mapping linear.
+    #[clap(
+        short,
+        long,
+        global = true,
+        default_value_t = 1.,
+        value_name = "N",
+        help_heading = "Device Options"
+    )]
+    exponent: f64,
+
+    /// Saturate the bottom end of the brightness range at this (raw) value
+    /// rather than zero. This is useful for systems that shut the backlight off
+    /// completely at zero, if you don't want them to do that.
+    #[clap(
+        long,
+        short,
+        global = true,
+        default_value_t = 0,
+        value_name = "RAW",
+        help_heading = "Device Options"
+    )]
+    min: u32,
+
+    /// Use a scale from 0 to MAX for all input and output instead of
+    /// percentages, like `0:255`, to match other programs. The exponent
+    /// applies as it does to percentages.
+    #[clap(
+        long,
+        global = true,
+        value_name = "0:MAX",
+        value_parser = parse_scale,
+        conflicts_with = "raw",
+        help_heading = "Device Options"
+    )]
+    scale: Option<u32>,
+
+    /// What percentage steps for `up`, `down` and `adjust` are a percentage
+    /// of: `max`, the device's whole range, or `current`, its current
+    /// setting. Steps relative to the current setting get smaller as the
+    /// brightness goes down.
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        value_name = "OF",
+        default_value_t = Relative::Max,
+        hide_possible_values = true
+    )]
+    relative: Relative,
+
+    /// Exit with a non-zero status if the device was already at the edge of its
+    /// range and could not be adjusted further, or if the driver accepted a
+    /// change but the hardware didn't follow (which can only be checked on
+    /// devices with `actual_brightness`). This can be useful for
+    /// detecting when the top or bottom of the scale has been reached, to
+    /// provide user feedback.
+    #[clap(short, long, global = true)]
+    picky: bool,
+
+    /// Ignore any brightness caps set in the config file, for deliberate
+    /// exceptions.
+    #[clap(long, global = true)]
+    override_cap: bool,
+
+    /// Fade to the new brightness over MS milliseconds, given like
+    /// `--smooth=500`, rather than jumping. Without a number, fades take a
+    /// quarter of a second. This only works on displays.
+    #[clap(
+        long,
+        global = true,
+        value_name = "MS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "250"
+    )]
+    smooth: Option<u64>,
+
+    /// Jump straight to the new brightness, even if the config file asks for
+    /// fades.
+    #[clap(long, global = true, conflicts_with = "smooth")]
+    instant: bool,
+
+    /// Don't use DBus at all, for containers and other systems without a
+    /// system bus. Reading the brightness works as usual; changes are written
+    /// to sysfs directly, which usually needs root or a udev rule.
+    #[clap(long, global = true, conflicts_with = "keyboard")]
+    no_dbus: bool,
+
+    /// Report failures on stderr as a single line of JSON, with fields
+    /// `code`, `error_code`, `message`, `device` and `hint`, for use by other
+    /// programs.
+    /// Mistakes in the command line itself are still reported as text.
+    #[clap(long, global = true)]
+    json_errors: bool,
+
+    #[clap(subcommand)]
+    cmd: SubCmd,
+}
+
+#[derive(Clone, Debug, Parser)]
+enum SubCmd {
+    /// Print the current backlight setting in the format "x/y", where x is the
+    /// current setting, and y is the max.
+    Get {
+        /// Exit with status 3 if the device is at its maximum, or 4 if it's at
+        /// or below the `--min` floor, so scripts can tell without parsing the
+        /// output.
+        #[clap(long)]
+        exit_status: bool,
+        /// Print only the current setting, without the maximum.
+        #[clap(long)]
+        bare: bool,
+    },
+    /// Show the backlight device in use, its current setting, and what any
+    /// configured policies are doing to it.
+    Status {
+        /// Keep running, and report again whenever anything changes. This also
+        /// reports devices being added or removed, and changes to their range
+        /// or power state. Policies are checked again every 30 seconds.
+        #[clap(short, long)]
+        watch: bool,
+        /// With `--watch`, how often to check for changes, in milliseconds.
+        /// Changes are found by polling, so this works with any driver; with
+        /// the `watch` feature, brightness changes show up right away.
+        #[clap(short, long, default_value_t = 1000, requires = "watch")]
+        interval: u64,
+        /// Report in JSON format. With `--watch`, each report or event is
+        /// printed as a single line, with an `event` field saying which it is.
+        #[clap(short, long)]
+        json: bool,
+    },
+    /// Set the backlight to a specific value.
+    Set {
+        /// New backlight value. Add a `%` or `r` suffix to give the value in
+        /// percent or raw units, regardless of `--raw`. Use `-` to read the
+        /// value from stdin.
+        value: Input,
+    },
+    /// Increase the backlight brightness relative to its current level,
+    /// saturating at the top of the device's range.
+    Up {
+        /// Amount to increase by. Add a `%` or `r` suffix to give the amount
+        /// in percent or raw units, regardless of `--raw`, or an `x` suffix to
+        /// multiply the raw setting by a factor, like `1.2x`.
+        by: Step,
+    },
+    /// Decrease the backlight brightness relative to its current level,
+    /// saturating at the requested minimum brightness level.
+    Down {
+        /// Amount to decrease by. Add a `%` or `r` suffix to give the amount
+        /// in percent or raw units, regardless of `--raw`, or an `x` suffix to
+        /// divide the raw setting by a factor, like `1.2x`.
+        by: Step,
+    },
+    /// List all the backlight devices, marking the one that other commands
+    /// would use with `*`.
+    List {
+        /// Leave out the header and highlighting, for use by scripts.
+        #[clap(long)]
+        plain: bool,
+    },
+    /// Print the setting of every device, for `import` to apply later.
+    ///
+    /// The exponent, minimum and config file cap in use are saved too, for
+    /// reference. Use `--name` to export just one device.
+    Export {
+        /// Format to write: `toml` or `json`.
+        #[clap(
+            short,
+            long,
+            value_enum,
+            default_value_t = export::Format::Toml,
+            hide_possible_values = true
+        )]
+        format: export::Format,
+    },
+    /// Set devices back to the settings saved by `export`.
+    ///
+    /// A device with a different range than the one exported is set to the
+    /// same fraction of its range. Caps and `--min` still apply. Use `--name`
+    /// to import just one device.
+    Import {
+        /// File to read, in either format, or `-` for stdin.
+        file: PathBuf,
+        /// Change all the devices or none: if any can't be set, put back the
+        /// ones already changed.
+        #[clap(long)]
+        atomic: bool,
+    },
+    /// Save the current settings under a name, and go back to them later.
+    ///
+    /// Snapshots cover every device, unless `--name` is given, and are kept in
+    /// `$XDG_STATE_HOME/brightr/snapshots`. Loading one works like `import`.
+    Snapshot {
+        #[clap(subcommand)]
+        action: snapshot::Action,
+    },
+    /// Measure how quickly each device can be read and changed.
+    ///
+    /// This times reading each device's setting, and changing it through
+    /// logind, to help choose sensible rates for fades and spot slow firmware.
+    /// The setting is written back unchanged, so this shouldn't be visible.
+    Bench {
+        /// Number of times to read and write each device.
+        #[clap(short, long, default_value_t = 20)]
+        iterations: usize,
+    },
+    /// Increase or decrease the backlight brightness by a signed amount.
+    ///
+    /// This is the same as `up` or `down`, depending on the sign, but can be
+    /// easier to use from scripts and keybinding configs.
+    Adjust {
+        /// Amount to change by, like `+10` or `-10`. Add a `%` or `r` suffix
+        /// to give the amount in percent or raw units, regardless of `--raw`,
+        /// or an `x` suffix to multiply or divide by a factor, like `+1.2x`.
+        #[clap(allow_hyphen_values = true)]
+        delta: Delta,
+    },
+    /// Switch the backlight fully off, through the device's `bl_power`.
+    ///
+    /// Some panels still glow at a brightness of 0; this cuts the backlight
+    /// completely, and keeps the brightness setting for `on`. logind can't do
+    /// this, so it needs write access to `bl_power` in sysfs: run as root, or
+    /// install a udev rule (see the README).
+    Off,
+    /// Switch the backlight back on after `off`, at the brightness it had.
+    On,
+    /// Read values from stdin, and set the backlight to each in turn.
+    ///
+    /// Values are separated by whitespace, and written as for `set`. If they
+    /// arrive faster than the interval, the ones in between are skipped, so
+    /// this is suitable for connecting a slider or dial straight to the
+    /// backlight. Stops at the end of input.
+    Stream {
+        /// Shortest time between changes, in milliseconds.
+        #[clap(short, long, default_value_t = 20)]
+        interval: u64,
+    },
+    /// Check that the device really responds to changes.
+    ///
+    /// This nudges the brightness by one real level (see `[steps]` in the
+    /// config), reads back what the hardware reports in `actual_brightness`,
+    /// and then restores the original setting. It tells apart not being
+    /// allowed to change the brightness from the driver ignoring changes.
+    TestDevice,
+    /// Find out how many distinct levels the device really has.
+    ///
+    /// This writes values spread across the device's range, and reads back
+    /// what the hardware reports for each, to spot drivers that offer a fine
+    /// range but round it to a few real levels. The original setting is
+    /// restored at the end. Caps don't apply.
+    MeasureSteps {
+        /// Number of values to write.
+        #[clap(short, long, default_value_t = 32)]
+        samples: u32,
+    },
+    /// Sweep the brightness slowly from the top of the range to the bottom.
+    ///
+    /// Each step prints the raw value and percentage, so you can note where
+    /// the panel becomes too dim to use or turns off, and choose `--min` and
+    /// `--exponent` to suit. Press Enter to pause and resume, or enter `q` to
+    /// stop. The original setting is restored at the end. Caps don't apply.
+    RampTest {
+        /// Number of steps to take.
+        #[clap(short, long, default_value_t = 20)]
+        steps: u32,
+        /// Time to spend on each step, in milliseconds.
+        #[clap(short, long, default_value_t = 500)]
+        delay: u64,
+    },
+    /// Set the brightness given by `boot` in the config file.
+    ///
+    /// This is meant to run early in boot, as root, before anyone has logged
+    /// in, so use `--backend sysfs`. Caps and `--min` still apply.
+    ApplyBoot {
+        /// Instead, print a systemd unit that runs this at boot, on the
+        /// device that would be used now. The unit reads the system-wide
+        /// config file, since the user's may not be mounted yet.
+        #[clap(long)]
+        print_unit: bool,
+        /// With `--print-unit`, the installed `brightr` for the unit to run.
+        /// Defaults to this one, if it's installed in a system directory
+        /// like /usr/local/bin.
+        #[clap(long, requires = "print_unit")]
+        binary: Option<PathBuf>,
+    },
+    /// Check the config file for mistakes.
+    CheckConfig,
+    /// Write a default, fully commented config file, if there isn't one
+    /// already.
+    InitConfig {
+        /// Replace any existing config file.
+        #[clap(short, long)]
+        force: bool,
+    },
+    /// Any other verb is looked up in the `[alias]` section of the config
+    /// file.
+    #[clap(external_subcommand)]
+    Alias(Vec<String>),
+}
+
+impl Brightr {
+    /// Returns the backends to try, in order: the one given on the command
+    /// line, or else the ones from `config`. With `--no-dbus`, backends that
+    /// need DBus are left out, and if that leaves nothing, we write to sysfs.
+    fn backends(&self, config: &Config) -> Vec<Backend> {
+        let chain = match &self.backend {
+            Some(backend) => std::slice::from_ref(backend),
+            None => config.backends(),
+        };
+        if !self.no_dbus {
+            return chain.to_vec();
+        }
+        let chain = chain
+            .iter()
+            .copied()
+            .filter(|backend| !backend.uses_dbus())
+            .collect::<Vec<_>>();
+        if chain.is_empty() {
+            vec![Backend::Sysfs]
+        } else {
+            chain
+        }
+    }
+
+    /// Returns the real name of the device to use: the one given on the
+    /// command line, or else the default from `config`, if either says. Only
+    /// the command line limits commands that cover every device, like
+    /// `export`.
+    fn device(&self, config: &Config) -> Option<String> {
+        if self.keyboard {
+            return None;
+        }
+        // `run` has already looked up the name given on the command line.
+        self.name.clone().or_else(|| {
+            let name = config.device.as_deref()?;
+            Some(config.device_name(name).to_owned())
+        })
+    }
+}
+
+fn main() -> ExitCode {
+    let mut device = None;
+    let mut status = ExitCode::SUCCESS;
+    let Err(e) = run(&mut device, &mut status) else {
+        return status;
+    };
+    let problem = Problem::of(&e);
+    let hint = problem.and_then(Problem::hint);
+    let e = match problem.and_then(Problem::summary) {
+        Some(summary) => e.context(summary),
+        None => e,
+    };
+    // We may fail before we've parsed the arguments (reading the config, for
+    // instance), so look for the flag ourselves.
+    if std::env::args_os().any(|arg| arg == "--json-errors") {
+        let error_code = e
+            .chain()
+            .find_map(|e| e.downcast_ref::<brightr::Error>())
+            .map(brightr::Error::code);
+        let report = serde_json::json!({
+            "code": problem.map_or("other", Problem::code),
+            "error_code": error_code,
+            "message": format!("{e:#}"),
+            "device": device,
+            "hint": hint,
+        });
+        eprintln!("{report}");
+    } else {
+        eprintln!("Error: {e:?}");
+        if let Some(hint) = hint {
+            eprintln!("\nhint: {hint}");
+        }
+    }
+    if problem.is_some_and(Problem::is_environment) {
+        ExitCode::from(UNSUPPORTED)
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// Does everything `main` does, minus reporting errors. Records the name of
+/// the device we're working on in `device` as soon as we know it, so errors can
+/// mention it, and sets `status` for commands that succeed with something other
+/// than the usual exit status.
+fn run(
+    device: &mut Option<String>,
+    status: &mut ExitCode,
+) -> anyhow::Result<()> {
+    // First, validate the arguments. We need the config file to do this, since
+    // it may define aliases. A broken config file mustn't get in the way of
+    // help, or the commands for fixing it, so those go ahead without it.
+    let (config, broken) = match Config::load() {
+        Ok(config) => (config, None),
+        Err(e) if !needs_config() => (Config::default(), Some(e)),
+        Err(e) => return Err(e),
+    };
+    let mut args = parse_args(&config);
+    if let Some(name) = &mut args.name {
+        *name = config.device_name(name).to_owned();
+    }
+
+    env_logger::init();
+
+    if let SubCmd::InitConfig { force } = args.cmd {
+        return init_config(force);
+    }
+
+    if let SubCmd::List { plain } = args.cmd {
+        let active = find_device(&args, &config, None)
+            .ok()
+            .map(|(bl, _)| bl.name);
+        list::run(&device_names(), active.as_deref(), args.exponent, plain);
+        return Ok(());
+    }
+
+    // These work on every device, unless one is named.
+    let names = match &args.name {
+        Some(name) => vec![name.clone()],
+        None => device_names(),
+    };
+    match &args.cmd {
+        SubCmd::Bench { iterations } => return bench::run(&names, *iterations),
+        SubCmd::Export { format } => {
+            let state = export::State::capture(&args, &config, &names);
+            return state.print(*format);
+        }
+        SubCmd::Import { file, atomic } => {
+            return export::read(file)?.restore(&args, &config, *atomic);
+        }
+        SubCmd::Snapshot { action } => {
+            return snapshot::run(&args, &config, &names, action);
+        }
+        _ => (),
+    }
+
+    // Checking the config doesn't need a backlight, so do it before looking
+    // for one. A config that can't be loaded at all has one problem: the
+    // error, which says where in the file it is.
+    if let SubCmd::CheckConfig = args.cmd {
+        let mut cmd = Brightr::command();
+        cmd.build();
+        let problems = match broken {
+            Some(e) => vec![format!("{e:#}")],
+            None => config.problems(&cmd),
+        };
+        for problem in &problems {
+            eprintln!("problem: {problem}");
+        }
+        if !problems.is_empty() {
+            bail!("found {} problem(s) in config", problems.len());
+        }
+        let system = config::system_path();
+        if system.exists() {
+            println!("system config file {} is OK", system.display());
+        }
+        match config::path() {
+            Some(path) if path.exists() => {
+                println!("config file {} is OK", path.display())
+            }
+            Some(path) => println!("no config file at {}", path.display()),
+            None => println!("can't locate config file; is HOME set?"),
+        }
+        return Ok(());
+    }
+
+    // Then, see if there is a supported and matching backlight device. This way
+    // we can warn the user if their system is unsupported, before presenting
+    // possibly confusing DBus errors.
+    let kbd = if args.keyboard {
+        Some(KbdBacklight::connect()?)
+    } else {
+        None
+    };
+    *device = args.device(&config);
+    let (mut bl, current) = find_device(&args, &config, kbd.as_ref())?;
+    if let Some(&step) = config.steps.get(&bl.name) {
+        bl.step = step.max(1);
+    }
+    *device = Some(bl.name.clone());
+
+    debug!("backlight raw setting = {current} / {}", bl.max);
+
+    // Map values into the appropriate unit depending on the arguments. Values
+    // given with an explicit unit suffix override this below.
+    let unit = match args.scale {
+        _ if args.raw => Unit::Raw,
+        Some(top) => Unit::Scale(top),
+        None => Unit::Percent,
+    };
+
+    debug!(
+        "in requested units: {} / {}",
+        unit.to_user(&bl, args.exponent, current),
+        unit.to_user(&bl, args.exponent, bl.max),
+    );
+
+    if let SubCmd::Get { exit_status, bare } = args.cmd {
+        let value = unit.to_user(&bl, args.exponent, current);
+        if bl.max == 1 && !args.raw {
+            // On/off devices have no brightness in between to speak of.
+            println!("{}", if current == 0 { "off" } else { "on" });
+        } else if bare {
+            println!("{value}");
+        } else {
+            println!("{value}/{}", unit.to_user(&bl, args.exponent, bl.max));
+        }
+        if exit_status {
+            if current >= bl.max {
+                *status = ExitCode::from(AT_MAX);
+            } else if current <= args.min {
+                *status = ExitCode::from(AT_FLOOR);
+            }
+        }
+        // No change required for this verb. In fact, we'll just skip the rest
+        // of the program, to simplify the common case below.
+        return Ok(());
+    }
+
+    if let SubCmd::TestDevice = args.cmd {
+        if kbd.is_some() {
+            bail!("test-device only works on displays");
+        }
+        return test_device::run(&args.backends(&config), &bl, current);
+    }
+
+    if let SubCmd::Off | SubCmd::On = args.cmd {
+        if kbd.is_some() {
+            bail!("on and off only work on displays");
+        }
+        let on = matches!(args.cmd, SubCmd::On);
+        return Ok(brightr::sysfs_set_power(&bl, on)?);
+    }
+
+    if let SubCmd::MeasureSteps { samples } = args.cmd {
+        if kbd.is_some() {
+            bail!("measure-steps only works on displays");
+        }
+        let chain = args.backends(&config);
+        let mut writer = backend::Writer::new(&chain);
+        return measure_steps::run(&mut writer, &bl, current, samples);
+    }
+
+    if let SubCmd::RampTest { steps, delay } = args.cmd {
+        if kbd.is_some() {
+            bail!("ramp-test only works on displays");
+        }
+        let chain = args.backends(&config);
+        let delay = Duration::from_millis(delay);
+        let mut writer = backend::Writer::new(&chain);
+        return ramp_test::run(&args, &mut writer, &bl, current, steps, delay);
+    }
+
+    if let SubCmd::ApplyBoot {
+        print_unit,
+        ref binary,
+    } = args.cmd
+    {
+        if kbd.is_some() {
+            bail!("apply-boot only works on displays");
+        }
+        if print_unit {
+            return boot::print_unit(&args, binary.as_deref(), &bl);
+        }
+    }
+
+    if let SubCmd::Status {
+        watch,
+        interval,
+        json,
+    } = args.cmd
+    {
+        let watch = watch.then(|| Duration::from_millis(interval));
+        return status::run(&args, &config, watch, json, bl, current, || {
+            find_device(&args, &config, kbd.as_ref())
+        });
+    }
+
+    // Work out what the user asked for. A stream asks for one thing after
+    // another, so there's nothing to say up front.
+    let adjustment = match args.cmd {
+        // Handled above.
+        SubCmd::Get { .. }
+        | SubCmd::Status { .. }
+        | SubCmd::List { .. }
+        | SubCmd::Bench { .. }
+        | SubCmd::Export { .. }
+        | SubCmd::Import { .. }
+        | SubCmd::Snapshot { .. }
+        | SubCmd::TestDevice
+        | SubCmd::Off
+        | SubCmd::On
+        | SubCmd::MeasureSteps { .. }
+        | SubCmd::RampTest { .. }
+        | SubCmd::CheckConfig
+        | SubCmd::InitConfig { .. } => unreachable!(),
+        // parse_args has expanded these already.
+        SubCmd::Alias(_) => unreachable!(),
+        SubCmd::Stream { .. } => None,
+        SubCmd::Set { value } => {
+            let value = value.read()?;
+            let unit = value.unit.unwrap_or(unit);
+            Some(set_adjustment(&bl, &config, unit, value.amount))
+        }
+        SubCmd::ApplyBoot { .. } => {
+            let Some(boot) = config.boot else {
+                bail!("no `boot` brightness in the config file");
+            };
+            // Like the cap, a bare number in the config file is a percentage.
+            let unit = boot.unit.unwrap_or(Unit::Percent);
+            Some(set_adjustment(&bl, &config, unit, boot.amount))
+        }
+        SubCmd::Up { by }
+        | SubCmd::Adjust {
+            delta: Delta { down: false, by },
+        } => Some(step_adjustment(
+            &args,
+            current,
+            unit,
+            Delta { down: false, by },
+        )),
+        SubCmd::Down { by }
+        | SubCmd::Adjust {
+            delta: Delta { down: true, by },
+        } => Some(step_adjustment(
+            &args,
+            current,
+            unit,
+            Delta { down: true, by },
+        )),
+    };
+
+    // Where that would take us, if nothing got in the way, for policies to
+    // consider.
+    let uncapped = AdjustOptions {
+        exponent: args.exponent,
+        min: args.min,
+        max: None,
+        clamp: Clamp::Saturate,
+    };
+    let requested = adjustment
+        .as_ref()
+        .map(|a| a.apply_with(&bl, current, &uncapped))
+        .transpose()?;
+
+    // Work out the highest value we're willing to write. This is usually the
+    // top of the device's range, unless one of the user's policies caps it
+    // lower.
+    let verdicts = if args.override_cap {
+        vec![]
+    } else {
+        let context = policy::Context {
+            backlight: &bl,
+            current,
+            target: requested,
+        };
+        policy::evaluate(&policy::from_config(&config), &context)
+    };
+    let cap_to_raw = |cap: Value| cap.to_raw(&bl, args.exponent, Unit::Percent);
+
+    for verdict in &verdicts {
+        if let Err(e) = &verdict.cap {
+            eprintln!("can't apply {} policy: {e}", verdict.policy);
+        }
+    }
+    let (ceiling, capped_by) = policy::ceiling(&verdicts, bl.max, cap_to_raw);
+    // If the cap and the floor cross, the cap wins.
+    let floor = args.min.min(ceiling);
+
+    debug!("allowed raw range = {floor} ..= {ceiling}");
+    let options = AdjustOptions {
+        exponent: args.exponent,
+        min: floor,
+        max: Some(ceiling),
+        clamp: if args.picky {
+            Clamp::Strict
+        } else {
+            Clamp::Saturate
+        },
+    };
+
+    // Changes the brightness to raw value `target`, and anything that should
+    // follow it. With `fade`, this fades there from `current`, showing how
+    // it's going if someone's watching.
+    let chain = args.backends(&config);
+    let mut writer = backend::Writer::new(&chain);
+    let progress = io::stdout().is_terminal();
+    let max = unit.to_user(&bl, args.exponent, bl.max);
+    let mut apply = |target: u32,
+                     fade: Option<Duration>|
+     -> anyhow::Result<()> {
+        match &kbd {
+            // Keyboard backlights only have the one way of getting there.
+            Some(kbd) => kbd.set_brightness(target)?,
+            None => {
+                match fade {
+                    Some(duration) => {
+                        fade::run(
+                            current,
+                            target,
+                            duration,
+                            Easing::default(),
+                            fade::DEFAULT_RATE,
+                            |value| -> anyhow::Result<()> {
+                                writer.set(&bl, value)?;
+                                if progress {
+                                    let now =
+                                        unit.to_user(&bl, args.exponent, value);
+                                    // Overwrite the line each time.
+                                    print!("\r\x1b[Kfading: {now}/{max}");
+                                    io::stdout().flush()?;
+                                }
+                                Ok(())
+                            },
+                        )?;
+                        if progress {
+                            println!();
+                        }
+                    }
+                    None => writer.set(&bl, target).map(drop)?,
+                }
+                if let Err(e) = keyboard::display_changed(
+                    &config.keyboard,
+                    &bl,
+                    args.exponent,
+                    target,
+                ) {
+                    eprintln!("can't adjust keyboard backlight: {e}");
+                }
+            }
+        }
+        Ok(())
+    };
+
+    if let SubCmd::Stream { interval } = args.cmd {
+        return stream::run(Duration::from_millis(interval), |value| {
+            let unit = value.unit.unwrap_or(unit);
+            let target = set_adjustment(&bl, &config, unit, value.amount)
+                .apply_with(&bl, current, &options)?;
+            apply(target, None)
+        });
+    }
+
+    let Some(adjustment) = adjustment else {
+        unreachable!("streams are handled above");
+    };
+    debug!("adjustment = {adjustment:?}");
+
+    let target = adjustment.apply_with(&bl, current, &options)?;
+    if let (Some(policy), Some(requested)) = (capped_by, requested) {
+        if requested > ceiling {
+            info!("{policy} policy limited brightness to {ceiling}");
+        }
+    }
+
+    debug!("target in raw units = {target}");
+    debug!(
+        "target in percentage = {}%)",
+        bl.percent_from_raw(target, args.exponent)
+    );
+
+    // Being picky, also check the hardware really changed. Reading it before
+    // and after, rather than comparing with what we wrote, copes with drivers
+    // that report the hardware's state on a different scale. Devices without
+    // actual_brightness only tell us what we wrote, so can't be checked.
+    let before = if args.picky && kbd.is_none() && target != current {
+        let before = bl.try_read_actual().ok().flatten();
+        if before.is_none() {
+            info!("{} has no actual_brightness to check the change", bl.name);
+        }
+        before
+    } else {
+        None
+    };
+    apply(target, args.smooth.map(Duration::from_millis))?;
+    if let Some(before) = before {
+        thread::sleep(test_device::SETTLE_TIME);
+        if bl.try_read_actual()? == Some(before) {
+            bail!(
+                "set brightness to {target}, but the device didn't change; \
+                 the driver seems to be ignoring it"
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Works out the change for taking `step` from raw setting `current`. `unit` is
+/// the unit to use if the step doesn't give one.
+fn step_adjustment(
+    args: &Brightr,
+    current: u32,
+    unit: Unit,
+    step: Delta,
+) -> Adjustment {
+    // Steps that go anywhere at all move at least one raw unit, even if they
+    // round to nothing, so the library makes sure they escape the current
+    // level.
+    let at_least_one = |by: f64| {
+        // This saturates on overflow, which is what we want.
+        let by = by.round() as u32;
+        Brightness::Raw(by.max(u32::from(!step.by.is_zero())))
+    };
+    let by = match step.by {
+        Step::By(by) => {
+            let unit = by.unit.unwrap_or(unit);
+            let amount = f64::from(by.amount);
+            match (unit, args.relative) {
+                // A percentage of the current setting doesn't depend on the
+                // curve, so we can work it out in raw units.
+                (Unit::Percent, Relative::Current) => {
+                    at_least_one(f64::from(current) * amount / 100.)
+                }
+                (Unit::Scale(top), Relative::Current) => {
+                    at_least_one(f64::from(current) * amount / f64::from(top))
+                }
+                _ => unit.brightness(by.amount),
+            }
+        }
+        Step::Times(factor) => {
+            let current = f64::from(current);
+            let scaled = if step.down {
+                current / factor
+            } else {
+                current * factor
+            };
+            at_least_one((scaled - current).abs())
+        }
+    };
+    if step.down {
+        Adjustment::Decrease(by)
+    } else {
+        Adjustment::Increase(by)
+    }
+}
+
+/// Works out the change for setting `bl` to `amount`, in `unit`.
+fn set_adjustment(
+    bl: &Backlight,
+    config: &Config,
+    unit: Unit,
+    amount: u32,
+) -> Adjustment {
+    // Devices that can only be on or off make percentages degenerate,
+    // particularly with an exponent, where anything short of 100% rounds to
+    // off. So for these, we compare percentages against a threshold. Steps
+    // need no such help: they always move at least one level, which switches
+    // the device on or off.
+    let threshold = f64::from(config.binary_threshold.unwrap_or(50)) / 100.;
+    let brightness = match unit.brightness(amount) {
+        Brightness::Percent(pct) if bl.max == 1 => {
+            Brightness::Raw(u32::from(pct / 100. >= threshold))
+        }
+        Brightness::Fraction(fraction) if bl.max == 1 => {
+            Brightness::Raw(u32::from(fraction >= threshold))
+        }
+        brightness => brightness,
+    };
+    Adjustment::Set(brightness)
+}
+
+/// Units that brightness values can be expressed in on the command line.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Unit {
+    /// The driver's own raw values.
+    Raw,
+    /// Percentage of the maximum, after applying the exponent.
+    Percent,
+    /// Like a percentage, but out of this number rather than 100, for
+    /// `--scale`.
+    Scale(u32),
+}
+
+impl Unit {
+    /// Converts `amount` in this unit into a `Brightness` for the library.
+    fn brightness(self, amount: u32) -> Brightness {
+        match self {
+            Unit::Raw => Brightness::Raw(amount),
+            Unit::Percent => Brightness::Percent(f64::from(amount)),
+            Unit::Scale(top) => {
+                Brightness::Fraction(f64::from(amount) / f64::from(top))
+            }
+        }
+    }
+
+    /// Converts a raw `value` for `bl` into this unit, using exponent `e`.
+    fn to_user(self, bl: &Backlight, e: f64, value: u32) -> u32 {
+        match self {
+            Unit::Raw => value,
+            Unit::Percent => bl.percent_from_raw(value, e),
+            Unit::Scale(top) => {
+                let fraction = bl.fraction_from_raw(value, e);
+                (fraction * f64::from(top)).round() as u32
+            }
+        }
+    }
+
+    /// Converts a `value` in this unit into a raw value for `bl`, using
+    /// exponent `e`.
+    fn to_raw(self, bl: &Backlight, e: f64, value: u32) -> u32 {
+        match self {
+            Unit::Raw => value,
+            Unit::Percent => bl.raw_from_percent(value, e),
+            Unit::Scale(top) => {
+                bl.raw_from_fraction(f64::from(value) / f64::from(top), e)
+            }
+        }
+    }
+}
+
+/// What percentage steps are a percentage of.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum Relative {
+    /// The top of the device's range.
+    Max,
+    /// The device's current setting.
+    Current,
+}
+
+/// A brightness value from the command line, with an optional unit suffix:
+/// `%` for percent or `r` for raw.
+#[derive(Copy, Clone, Debug)]
+struct Value {
+    amount: u32,
+    /// Unit given explicitly by the user, if any.
+    unit: Option<Unit>,
+}
+
+impl Value {
+    /// Converts this value into a raw value for `bl`, using exponent `e`. If
+    /// the value doesn't specify a unit, it's taken to be in `default`.
+    fn to_raw(self, bl: &Backlight, e: f64, default: Unit) -> u32 {
+        self.unit.unwrap_or(default).to_raw(bl, e, self.amount)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let suffix = match self.unit {
+            // Scales only come from `--scale`, never a suffix.
+            None | Some(Unit::Scale(_)) => "",
+            Some(Unit::Percent) => "%",
+            Some(Unit::Raw) => "r",
+        };
+        write!(f, "{}{suffix}", self.amount)
+    }
+}
+
+impl FromStr for Value {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (digits, unit) = if let Some(digits) = s.strip_suffix('%') {
+            (digits, Some(Unit::Percent))
+        } else if let Some(digits) = s.strip_suffix('r') {
+            (digits, Some(Unit::Raw))
+        } else {
+            (s, None)
+        };
+        Ok(Value {
+            amount: digits.parse()?,
+            unit,
+        })
+    }
+}
+
+/// A value from the command line that may instead be read from stdin.
+#[derive(Copy, Clone, Debug)]
+enum Input {
+    /// The value was given directly.
+    Given(Value),
+    /// The user passed `-`, so we should read the value from stdin.
+    Stdin,
+}
+
+impl Input {
+    /// Returns the value, reading it from stdin if needed.
+    fn read(self) -> anyhow::Result<Value> {
+        match self {
+            Input::Given(value) => Ok(value),
+            Input::Stdin => {
+                let text = io::read_to_string(io::stdin())
+                    .context("can't read value from stdin")?;
+                let text = text.trim();
+                text.parse()
+                    .with_context(|| format!("bad value on stdin: `{text}`"))
+            }
+        }
+    }
+}
+
+impl FromStr for Input {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "-" {
+            Ok(Input::Stdin)
+        } else {
+            s.parse().map(Input::Given)
+        }
+    }
+}
+
+/// The size of a step up or down on the command line.
+#[derive(Copy, Clone, Debug)]
+enum Step {
+    /// Move by a fixed amount, written like any other `Value`.
+    By(Value),
+    /// Multiply (going up) or divide (going down) the raw setting by this
+    /// factor, which is at least 1. Written like `1.2x`.
+    Times(f64),
+}
+
+impl Step {
+    /// Checks whether this step doesn't actually go anywhere.
+    fn is_zero(self) -> bool {
+        match self {
+            Step::By(value) => value.amount == 0,
+            Step::Times(factor) => factor == 1.,
+        }
+    }
+}
+
+impl FromStr for Step {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(factor) = s.strip_suffix('x') else {
+            return s.parse().map(Step::By).map_err(|e| e.to_string());
+        };
+        let factor = factor.parse::<f64>().map_err(|e| e.to_string())?;
+        if !(1. ..=f64::MAX).contains(&factor) {
+            return Err("factor must be at least 1".to_string());
+        }
+        Ok(Step::Times(factor))
+    }
+}
+
+/// A signed step from the command line: a `Step` preceded by `+` or `-`. A
+/// missing sign means `+`.
+#[derive(Copy, Clone, Debug)]
+struct Delta {
+    /// Whether the change is downwards.
+    down: bool,
+    /// Size of the change.
+    by: Step,
+}
+
+impl FromStr for Delta {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (down, rest) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        Ok(Delta {
+            down,
+            by: rest.parse()?,
+        })
+    }
+}
+
+/// Finds the device that the arguments ask us to adjust, and returns it along
+/// with its current raw setting. If `kbd` is provided, that's the device.
+fn find_device(
+    args: &Brightr,
+    config: &Config,
+    kbd: Option<&KbdBacklight>,
+) -> anyhow::Result<(Backlight, u32)> {
+    if let Some(kbd) = kbd {
+        // UPower doesn't tell us the underlying device's name.
+        let bl = Backlight {
+            name: "UPower keyboard backlight".to_string(),
+            subsystem: Subsystem::Leds,
+            max: kbd.max_brightness()?,
+            step: 1,
+            source: BrightnessFile::Brightness,
+        };
+        return Ok((bl, kbd.brightness()?));
+    }
+    // wscons has a single display, with no name, measured in percent.
+    #[cfg(target_os = "openbsd")]
+    let found = {
+        if args.device(config).is_some() {
+            bail!("--name isn't supported on OpenBSD");
+        }
+        let bl = Backlight {
+            name: "wscons display".to_string(),
+            subsystem: Subsystem::Backlight,
+            max: 100,
+            step: 1,
+            source: BrightnessFile::Brightness,
+        };
+        (bl, brightr::wscons::get_brightness()?)
+    };
+    #[cfg(not(target_os = "openbsd"))]
+    let found = {
+        let (mut bl, mut current) = match &args.device(config) {
+            Some(name) => brightr::use_specific_backlight(name.clone())
+                .or_else(|e| match find_by_connector(name) {
+                    Some(found) if e.is_no_device() => Ok(found),
+                    _ => Err(e),
+                })
+                .map_err(|e| explain_missing_device(name, e))?,
+            None => brightr::find_first_backlight()?,
+        };
+        if args.actual {
+            current = bl.prefer_actual()?;
+            debug!("reading the setting from {}", bl.source.file_name());
+        }
+        (bl, current)
+    };
+    Ok(found)
+}
+
+/// Finds the backlight device lighting the display connector `name`, given
+/// either in full, like `card1-eDP-1`, or as just the output, like `eDP-1`.
+#[cfg(not(target_os = "openbsd"))]
+fn find_by_connector(name: &str) -> Option<(Backlight, u32)> {
+    let found = brightr::enumerate_backlights().ok()?;
+    found.into_iter().find(|(bl, _)| {
+        bl.connector().is_some_and(|connector| {
+            connector == name
+                || connector.split_once('-').is_some_and(|(_, o)| o == name)
+        })
+    })
+}
+
+/// Lists the names of the backlight devices in sysfs, sorted.
+fn device_names() -> Vec<String> {
+    let Ok(dir) = fs::read_dir(brightr::backlight_dir()) else {
+        return vec![];
+    };
+    let mut names = dir
+        .filter_map(|d| d.ok()?.file_name().into_string().ok())
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+/// Finds the one of `names` most like `name`, if any are much like it, to
+/// suggest when `name` looks like a typo.
+fn closest_name<'a>(name: &str, names: &'a [String]) -> Option<&'a str> {
+    // This is the same measure and threshold clap uses for its suggestions.
+    names
+        .iter()
+        .map(|candidate| (strsim::jaro(name, candidate), candidate))
+        .filter(|(score, _)| *score > 0.7)
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, candidate)| candidate.as_str())
+}
+
+/// Turns error `e`, from trying to use the device `name`, into something more
+/// helpful if the device doesn't exist: a suggestion of what the user may have
+/// meant, or failing that, the devices that do exist.
+#[cfg(not(target_os = "openbsd"))]
+fn explain_missing_device(name: &str, e: brightr::Error) -> anyhow::Error {
+    if brightr::backlight_dir().join(name).exists() {
+        return e.into();
+    }
+    // Keep the original error underneath, so hints can see what happened.
+    let e = anyhow::Error::new(e);
+    let names = device_names();
+    match closest_name(name, &names) {
+        Some(candidate) => e.context(format!(
+            "no backlight device `{name}`; did you mean `{candidate}`?"
+        )),
+        None if names.is_empty() => {
+            e.context(format!("no backlight device `{name}`, or any others"))
+        }
+        None => e.context(format!(
+            "no backlight device `{name}`; devices found: {}",
+            names.join(", ")
+        )),
+    }
+}
+
+/// Implementation of the `init-config` command.
+fn init_config(force: bool) -> anyhow::Result<()> {
+    let Some(path) = config::path() else {
+        bail!("can't locate config file; is HOME set?");
+    };
+    if path.exists() && !force {
+        bail!(
+            "config file {} already exists; use --force to replace it",
+            path.display()
+        );
+    }
+
+    let mut text = config::TEMPLATE.to_string();
+    // List the devices we can see, to save the user hunting for names to pass
+    // to --name.
+    let names = device_names();
+    if !names.is_empty() {
+        text += "\n# Backlight devices found on this machine, for use with \
+                 --name:\n";
+        for name in names {
+            text += &format!("#   {name}\n");
+        }
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("can't create {}", dir.display()))?;
+    }
+    fs::write(&path, text)
+        .with_context(|| format!("can't write {}", path.display()))?;
+    println!("wrote default config to {}", path.display());
+    Ok(())
+}
+
+/// Checks whether the command line needs the config file, which everything
+/// does but help, `check-config` and `init-config`. This looks at the raw
+/// arguments, since it's needed before they can be parsed.
+fn needs_config() -> bool {
+    const WITHOUT: [&str; 5] =
+        ["check-config", "init-config", "help", "--help", "-h"];
+    !std::env::args_os()
+        .skip(1)
+        .any(|arg| WITHOUT.iter().any(|w| arg == *w))
+}
+
+/// Parses the command line, expanding any user-defined aliases from `config`.
+///
+/// Aliases are only consulted for verbs that aren't built in, so an alias can't
+/// change the meaning of an existing command. An alias may expand to another
+/// alias, but not (eventually) to itself.
+fn parse_args(config: &Config) -> Brightr {
+    let mut argv = match compat::translate(std::env::args_os().collect()) {
+        Ok(argv) => argv,
+        Err(e) => Brightr::command()
+            .error(ErrorKind::UnknownArgument, e)
+            .exit(),
+    };
+    let mut expanded = BTreeSet::new();
+    loop {
+        let matches = Brightr::command().get_matches_from(&argv);
+        let mut args =
+            Brightr::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        let SubCmd::Alias(words) = &args.cmd else {
+            // The config file's settings stand in for options not given.
+            let given =
+                |id| matches.value_source(id) == Some(ValueSource::CommandLine);
+            if let Some(exponent) =
+                config.exponent.filter(|_| !given("exponent"))
+            {
+                args.exponent = exponent;
+            }
+            if let Some(min) = config.min.filter(|_| !given("min")) {
+                args.min = min;
+            }
+            if args.smooth.is_none() && !args.instant {
+                args.smooth = config.smooth;
+            }
+            return args;
+        };
+        // clap always gives us at least the verb itself.
+        let name = &words[0];
+        let Some(expansion) = config.alias.get(name) else {
+            Brightr::command()
+                .error(
+                    ErrorKind::InvalidSubcommand,
+                    format!("unrecognized subcommand '{name}'"),
+                )
+                .exit()
+        };
+        if !expanded.insert(name.clone()) {
+            Brightr::command()
+                .error(
+                    ErrorKind::InvalidSubcommand,
+                    format!("alias '{name}' expands to itself"),
+                )
+                .exit()
+        }
+
+        // The external subcommand swallows the rest of the command line, so the
+        // alias and its arguments are always found at the end of argv.
+        argv.truncate(argv.len() - words.len());
+        argv.extend(expansion.split_whitespace().map(OsString::from));
+        argv.extend(words[1..].iter().map(OsString::from));
+    }
+}
+
+/// Parses the `--scale` option, returning the top of the scale.
+fn parse_scale(s: &str) -> Result<u32, String> {
+    let Some(top) = s.strip_prefix("0:") else {
+        return Err("expected a scale like `0:255`".to_string());
+    };
+    match top.parse() {
+        Ok(0) => Err("the scale can't end at 0".to_string()),
+        Ok(top) => Ok(top),
+        Err(e) => Err(e.to_string()),
+    }
+}
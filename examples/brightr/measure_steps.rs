@@ -0,0 +1,97 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The `measure-steps` command, which finds out how many distinct levels a
+//! device really has.
+//!
+//! Some drivers offer a huge raw range, like 0 to 65535, but the hardware only
+//! has a few levels, and rounds every write to the nearest. The driver usually
+//! reports the rounded level in `actual_brightness`, so by writing a spread of
+//! values and reading that back, we can see how coarse the steps really are.
+
+use crate::{backend::Writer, test_device::SETTLE_TIME};
+use anyhow::{bail, Context};
+use brightr::Backlight;
+use std::{collections::BTreeSet, thread};
+
+/// Runs the `measure-steps` command on `bl`, currently at raw setting
+/// `current`, writing `samples` values spread evenly across its range.
+pub fn run(
+    writer: &mut Writer<'_>,
+    bl: &Backlight,
+    current: u32,
+    samples: u32,
+) -> anyhow::Result<()> {
+    if samples < 2 {
+        bail!("need at least two samples");
+    }
+    // With a single raw value, there's nothing to spread the samples over.
+    if bl.max == 0 {
+        bail!("{} has a max of 0, so it has only one level", bl.name);
+    }
+    // Without actual_brightness, we'd only read back what we wrote.
+    if !bl.path().join("actual_brightness").exists() {
+        bail!(
+            "{} has no actual_brightness, so there's no way to tell what the \
+             hardware did with a change",
+            bl.name
+        );
+    }
+    let samples = samples.min(bl.max.saturating_add(1));
+
+    let mut levels = BTreeSet::new();
+    let mut result = Ok(());
+    for i in 0..samples {
+        let raw =
+            (u64::from(bl.max) * u64::from(i) / u64::from(samples - 1)) as u32;
+        if let Err(e) = writer.set(bl, raw) {
+            result = Err(e.into());
+            break;
+        }
+        thread::sleep(SETTLE_TIME);
+        match bl.read_actual() {
+            Ok(actual) => {
+                levels.insert(actual);
+            }
+            Err(e) => {
+                result = Err(e).context("can't read the device back");
+                break;
+            }
+        }
+    }
+
+    // Put things back before reporting, whatever happened.
+    if let Err(e) = writer.set(bl, current) {
+        eprintln!("can't restore the original setting: {e:#}");
+    }
+    result?;
+
+    let distinct = levels.len() as u32;
+    println!("device:   {}", bl.name);
+    println!("range:    0 to {} raw", bl.max);
+    println!("written:  {samples} values");
+    println!("readback: {distinct} distinct levels");
+    if distinct < samples {
+        // Rounding to the nearest level, values this far apart can land on
+        // the same one.
+        let step = bl.max / (distinct.max(2) - 1);
+        println!(
+            "the hardware has about {distinct} levels, so steps smaller than \
+             about {step} raw may not be visible"
+        );
+        println!(
+            "\nTo have `up` and `down` always move by a visible amount, add \
+             this to the config file:\n\n[steps]\n{} = {step}",
+            bl.name
+        );
+    } else if samples <= bl.max {
+        println!(
+            "every value written gave a different level; try more samples \
+             to look closer"
+        );
+    } else {
+        println!("every raw value is a distinct level");
+    }
+    Ok(())
+}
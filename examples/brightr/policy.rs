@@ -0,0 +1,244 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Policies that limit the brightness the user can ask for.
+//!
+//! Each policy looks at one aspect of the system (the config file, the battery,
+//! the temperature...) and decides whether the brightness should be capped,
+//! given a `Context` saying which device is changing and where to.
+//! `evaluate` collects their answers and `ceiling` combines them. To add a new
+//! behavior, implement `Policy` and add it to the list built by `from_config`.
+//!
+//! When sources of brightness settings conflict, the rules are:
+//!
+//! - The value the user asks for on the command line is what we aim for.
+//!   Policies can only limit it, never raise it or pick a value of their own.
+//! - Caps stack: when several policies apply, the lowest cap wins. Ties go to
+//!   the policy listed first by `from_config`.
+//! - A cap beats the `--min` floor if the two cross.
+//! - `--override-cap` switches off all policies for one invocation.
+//!
+//! When a cap changes what the user asked for, we log which policy was
+//! responsible.
+
+#[cfg(feature = "scripting")]
+use crate::Unit;
+use crate::{
+    config::{Config, LowBattery, Thermal},
+    power, thermal, Value,
+};
+use brightr::Backlight;
+use log::debug;
+
+/// What a policy can take into account when deciding on a cap.
+#[derive(Clone, Copy, Debug)]
+pub struct Context<'a> {
+    /// The device being changed.
+    pub backlight: &'a Backlight,
+    /// The device's raw setting before the change.
+    pub current: u32,
+    /// The raw setting the user asked for, before any cap, or `None` when
+    /// there's no change in mind, as for `status`.
+    pub target: Option<u32>,
+}
+
+/// A rule that may cap the brightness.
+pub trait Policy {
+    /// Short name for the policy, used to explain decisions in log output.
+    fn name(&self) -> &'static str;
+
+    /// Decides whether this policy wants to cap the brightness right now, in
+    /// `context`, and if so, at what level.
+    ///
+    /// Errors mean the policy couldn't check the thing it cares about. They're
+    /// reported, but don't stop other policies from being applied.
+    fn cap(&self, context: &Context<'_>) -> anyhow::Result<Option<Value>>;
+}
+
+/// Builds the list of policies requested by `config`.
+pub fn from_config(config: &Config) -> Vec<Box<dyn Policy + '_>> {
+    let mut policies: Vec<Box<dyn Policy + '_>> = vec![];
+    if let Some(cap) = config.cap {
+        policies.push(Box::new(Fixed(cap)));
+    }
+    if let Some(low) = &config.low_battery {
+        policies.push(Box::new(low));
+    }
+    if let Some(hot) = &config.thermal {
+        policies.push(Box::new(hot));
+    }
+    if let Some(source) = &config.script {
+        #[cfg(feature = "scripting")]
+        policies.push(Box::new(Script(source)));
+        #[cfg(not(feature = "scripting"))]
+        {
+            let _ = source;
+            eprintln!(
+                "ignoring script policy: brightr was built without the \
+                 `scripting` feature"
+            );
+        }
+    }
+    policies
+}
+
+/// What a single policy decided.
+#[derive(Debug)]
+pub struct Verdict {
+    /// Name of the policy.
+    pub policy: &'static str,
+    /// The cap it asked for, if any, or why it couldn't decide.
+    pub cap: anyhow::Result<Option<Value>>,
+}
+
+/// Runs all of `policies` in `context`, collecting their verdicts.
+pub fn evaluate(
+    policies: &[Box<dyn Policy + '_>],
+    context: &Context<'_>,
+) -> Vec<Verdict> {
+    debug!(
+        "checking policies for {} at {}, heading for {:?}",
+        context.backlight.name, context.current, context.target
+    );
+    policies
+        .iter()
+        .map(|policy| Verdict {
+            policy: policy.name(),
+            cap: policy.cap(context),
+        })
+        .collect()
+}
+
+/// Combines the caps in `verdicts` into a single raw ceiling, which is the
+/// lowest cap requested, or `max` if none are. Also returns the name of the
+/// policy that set the ceiling, if any. Policies that failed are skipped.
+///
+/// `to_raw` converts caps into raw units for comparison.
+pub fn ceiling(
+    verdicts: &[Verdict],
+    max: u32,
+    to_raw: impl Fn(Value) -> u32,
+) -> (u32, Option<&'static str>) {
+    let mut ceiling = (max, None);
+    for verdict in verdicts {
+        if let Ok(Some(cap)) = verdict.cap {
+            let cap = to_raw(cap);
+            debug!("policy {} caps brightness at {cap}", verdict.policy);
+            if cap < ceiling.0 {
+                ceiling = (cap, Some(verdict.policy));
+            }
+        }
+    }
+    ceiling
+}
+
+/// A cap that always applies.
+struct Fixed(Value);
+
+impl Policy for Fixed {
+    fn name(&self) -> &'static str {
+        "cap"
+    }
+
+    fn cap(&self, _: &Context<'_>) -> anyhow::Result<Option<Value>> {
+        Ok(Some(self.0))
+    }
+}
+
+impl Policy for &LowBattery {
+    fn name(&self) -> &'static str {
+        "low_battery"
+    }
+
+    fn cap(&self, _: &Context<'_>) -> anyhow::Result<Option<Value>> {
+        match power::battery()? {
+            Some(battery)
+                if battery.discharging && battery.percentage < self.below =>
+            {
+                self.cap_at(battery.percentage).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+impl Policy for &Thermal {
+    fn name(&self) -> &'static str {
+        "thermal"
+    }
+
+    fn cap(&self, _: &Context<'_>) -> anyhow::Result<Option<Value>> {
+        let hot = thermal::hottest(self.zone.as_deref())?
+            .is_some_and(|temp| temp > self.above);
+        Ok(hot.then_some(self.cap))
+    }
+}
+
+/// A policy written by the user in Rhai.
+///
+/// The script can use the variables `on_battery`, `battery` (charge
+/// percentage) and `temperature` (of the hottest thermal zone, in degrees
+/// Celsius), as well as `device`, and `current`, `target` and `max` in raw
+/// units, from the `Context`. `battery`, `temperature` and `target` are `()`
+/// if unavailable. The script's
+/// result is the cap: either a number (taken as a percentage), a string written
+/// like a command line value, or `()` for no cap.
+#[cfg(feature = "scripting")]
+struct Script<'a>(&'a str);
+
+#[cfg(feature = "scripting")]
+impl Policy for Script<'_> {
+    fn name(&self) -> &'static str {
+        "script"
+    }
+
+    fn cap(&self, context: &Context<'_>) -> anyhow::Result<Option<Value>> {
+        use rhai::{Dynamic, Engine, Scope};
+
+        let battery = power::battery()?;
+        let temperature = thermal::hottest(None)?;
+
+        let mut scope = Scope::new();
+        scope.push_constant(
+            "on_battery",
+            battery.is_some_and(|b| b.discharging),
+        );
+        scope.push_constant(
+            "battery",
+            battery.map_or(Dynamic::UNIT, |b| b.percentage.into()),
+        );
+        scope.push_constant(
+            "temperature",
+            temperature.map_or(Dynamic::UNIT, Dynamic::from),
+        );
+        let bl = context.backlight;
+        scope.push_constant("device", bl.name.clone());
+        scope.push_constant("current", i64::from(context.current));
+        scope.push_constant(
+            "target",
+            context
+                .target
+                .map_or(Dynamic::UNIT, |t| Dynamic::from(i64::from(t))),
+        );
+        scope.push_constant("max", i64::from(bl.max));
+
+        let result = Engine::new()
+            .eval_with_scope::<Dynamic>(&mut scope, self.0)
+            .map_err(|e| anyhow::anyhow!("{e}"))?;
+
+        if result.is_unit() {
+            Ok(None)
+        } else if let Ok(pct) = result.as_int() {
+            let amount = u32::try_from(pct)?;
+            Ok(Some(Value {
+                amount,
+                unit: Some(Unit::Percent),
+            }))
+        } else if let Some(text) = result.read_lock::<rhai::ImmutableString>() {
+            Ok(Some(text.parse()?))
+        } else {
+            anyhow::bail!("script returned a {}", result.type_name())
+        }
+    }
+}
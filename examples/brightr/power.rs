@@ -0,0 +1,54 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Power supply state, as reported by UPower.
+
+use zbus::blocking::Connection;
+
+/// The subset of UPower's `Device` interface we care about. We only ever talk
+/// to the "display device," which is UPower's summary of all the batteries in
+/// the system.
+#[zbus::proxy(
+    interface = "org.freedesktop.UPower.Device",
+    default_service = "org.freedesktop.UPower",
+    default_path = "/org/freedesktop/UPower/devices/DisplayDevice"
+)]
+trait Device {
+    #[zbus(property)]
+    fn is_present(&self) -> zbus::Result<bool>;
+    #[zbus(property)]
+    fn percentage(&self) -> zbus::Result<f64>;
+    #[zbus(property)]
+    fn state(&self) -> zbus::Result<u32>;
+}
+
+/// UPower device states that mean we're running on battery power.
+const DISCHARGING_STATES: [u32; 3] = [
+    2, // Discharging
+    3, // Empty
+    6, // Pending discharge
+];
+
+/// Summary of the system's batteries.
+#[derive(Copy, Clone, Debug)]
+pub struct Battery {
+    /// Whether we're currently running on battery power.
+    pub discharging: bool,
+    /// Remaining charge, as a percentage.
+    pub percentage: f64,
+}
+
+/// Asks UPower about the system's batteries. Returns `None` if there aren't
+/// any.
+pub fn battery() -> zbus::Result<Option<Battery>> {
+    let conn = Connection::system()?;
+    let device = DeviceProxyBlocking::new(&conn)?;
+    if !device.is_present()? {
+        return Ok(None);
+    }
+    Ok(Some(Battery {
+        discharging: DISCHARGING_STATES.contains(&device.state()?),
+        percentage: device.percentage()?,
+    }))
+}
@@ -0,0 +1,99 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The `ramp-test` command, which sweeps a device slowly down through its
+//! range so the user can see where it becomes unusable.
+//!
+//! Panels differ a lot at the bottom end: some turn off well before zero, and
+//! some are too dim to use long before they turn off. Watching the sweep, and
+//! noting the values where that happens, is the easiest way to choose `--min`
+//! and `--exponent`.
+
+use crate::{backend::Writer, Brightr};
+use anyhow::bail;
+use brightr::Backlight;
+use std::{
+    io::{self, BufRead},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+
+/// Runs the `ramp-test` command on `bl`, currently at raw setting `current`,
+/// taking `steps` steps from the top of the range to the bottom, `delay`
+/// apart.
+///
+/// Pressing Enter pauses and resumes the sweep, and entering `q` stops it.
+/// Either way, the original setting is restored at the end.
+pub fn run(
+    args: &Brightr,
+    writer: &mut Writer<'_>,
+    bl: &Backlight,
+    current: u32,
+    steps: u32,
+    delay: Duration,
+) -> anyhow::Result<()> {
+    if steps == 0 {
+        bail!("need at least one step");
+    }
+    // Reading stdin blocks, so do it elsewhere and let the sweep check in
+    // between steps.
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    println!("sweeping {} from the top; Enter pauses, q quits", bl.name);
+    let mut result = Ok(());
+    'sweep: for step in (0..=steps).rev() {
+        let pct = step * 100 / steps;
+        let raw = bl.raw_from_percent(pct, args.exponent);
+        if let Err(e) = writer.set(bl, raw) {
+            result = Err(e.into());
+            break;
+        }
+        println!(
+            "{raw:>6}/{}  {:>3}%",
+            bl.max,
+            bl.percent_from_raw(raw, args.exponent)
+        );
+
+        let mut paused = false;
+        loop {
+            let line = if paused {
+                rx.recv().map_err(|_| RecvTimeoutError::Disconnected)
+            } else {
+                rx.recv_timeout(delay)
+            };
+            match line {
+                Ok(line) if line.trim() == "q" => break 'sweep,
+                Ok(_) => {
+                    paused = !paused;
+                    if paused {
+                        println!("paused; Enter resumes");
+                    }
+                }
+                // Without input, just keep going at the usual pace.
+                Err(RecvTimeoutError::Disconnected) => {
+                    if !paused {
+                        thread::sleep(delay);
+                    }
+                    break;
+                }
+                Err(RecvTimeoutError::Timeout) => break,
+            }
+        }
+    }
+
+    match writer.set(bl, current) {
+        Ok(_) => println!("restored {current}/{}", bl.max),
+        Err(e) => eprintln!("can't restore the original setting: {e:#}"),
+    }
+    result
+}
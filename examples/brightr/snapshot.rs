@@ -0,0 +1,113 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The `snapshot` command, which saves the current settings of every device
+//! under a name, to go back to later.
+//!
+//! Snapshots are stored in the same format as `export` writes, one file per
+//! snapshot.
+
+use crate::{config::Config, export, Brightr};
+use anyhow::{bail, Context};
+use std::{fs, io, path::PathBuf};
+
+/// What to do with snapshots.
+#[derive(Clone, Debug, clap::Subcommand)]
+pub enum Action {
+    /// Save the current settings of every device, replacing any snapshot
+    /// with the same name.
+    Save {
+        /// Name to save the snapshot under.
+        // Not `name`, which would clash with the global `--name`.
+        #[clap(value_name = "NAME")]
+        snapshot: String,
+    },
+    /// Set every device back to the settings in a snapshot.
+    Load {
+        /// Name of the snapshot.
+        #[clap(value_name = "NAME")]
+        snapshot: String,
+        /// Change all the devices or none: if any can't be set, put back the
+        /// ones already changed.
+        #[clap(long)]
+        atomic: bool,
+    },
+    /// List the saved snapshots.
+    List,
+}
+
+/// Runs the `snapshot` command. `names` are the devices to save.
+pub fn run(
+    args: &Brightr,
+    config: &Config,
+    names: &[String],
+    action: &Action,
+) -> anyhow::Result<()> {
+    let Some(dir) = dir() else {
+        bail!("can't locate snapshot directory; is HOME set?");
+    };
+    match action {
+        Action::Save { snapshot } => {
+            let state = export::State::capture(args, config, names);
+            let text = toml::to_string(&state)?;
+            fs::create_dir_all(&dir)
+                .with_context(|| format!("can't create {}", dir.display()))?;
+            let path = dir.join(file_name(snapshot)?);
+            fs::write(&path, text)
+                .with_context(|| format!("can't write {}", path.display()))
+        }
+        Action::Load { snapshot, atomic } => {
+            let path = dir.join(file_name(snapshot)?);
+            if !path.exists() {
+                bail!("no snapshot named `{snapshot}`");
+            }
+            export::read(&path)?.restore(args, config, *atomic)
+        }
+        Action::List => {
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+                Err(e) => {
+                    return Err(e).with_context(|| {
+                        format!("can't read {}", dir.display())
+                    })
+                }
+            };
+            let mut saved = entries
+                .filter_map(|d| {
+                    let name = d.ok()?.file_name().into_string().ok()?;
+                    Some(name.strip_suffix(".toml")?.to_owned())
+                })
+                .collect::<Vec<_>>();
+            saved.sort();
+            for name in saved {
+                println!("{name}");
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Works out the file that snapshot `name` lives in, within the snapshot
+/// directory, rejecting names that would put it anywhere else.
+fn file_name(name: &str) -> anyhow::Result<String> {
+    if name.is_empty() || name.starts_with('.') || name.contains('/') {
+        bail!("bad snapshot name `{name}`");
+    }
+    Ok(format!("{name}.toml"))
+}
+
+/// Works out where snapshots should live. Like the config file, this follows
+/// the XDG base directory conventions; snapshots count as state rather than
+/// configuration.
+///
+/// Returns `None` if we can't work out a location at all, which basically
+/// means `HOME` isn't set.
+fn dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".local/state"),
+    };
+    Some(base.join("brightr").join("snapshots"))
+}
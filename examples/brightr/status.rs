@@ -0,0 +1,468 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Implementation of the `status` command.
+
+use crate::{
+    backend::{self, Backend},
+    config::Config,
+    device_names,
+    policy::{self, Verdict},
+    Brightr, Unit,
+};
+use brightr::Backlight;
+use log::debug;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fmt, fs, thread,
+    time::{Duration, Instant},
+};
+
+/// How often `status --watch` checks policies again. The things they look at,
+/// like the battery, change slowly, and some are costly to check, like asking
+/// UPower over DBus or running a script.
+const POLICY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Snapshot of everything `status` reports.
+#[derive(Debug, PartialEq, Serialize)]
+struct Report {
+    device: String,
+    /// The kernel's `type` for the device, if it has one.
+    kind: Option<String>,
+    /// The kernel driver behind the device, if known.
+    driver: Option<String>,
+    /// The display connector the device lights, if known.
+    connector: Option<String>,
+    raw: u32,
+    max: u32,
+    percent: u32,
+    /// Backend we'd use to change the brightness, if any seem to work.
+    backend: Option<&'static str>,
+    /// The logind session that would receive changes, if we can find one.
+    session: Option<SessionReport>,
+    /// Raw ceiling imposed by policies, if any.
+    ceiling: Option<u32>,
+    /// Policy responsible for `ceiling`.
+    capped_by: Option<&'static str>,
+    policies: Vec<PolicyReport>,
+}
+
+/// Something `status --watch` noticed happening.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+enum Event<'a> {
+    /// The status of the device in use changed.
+    Status(&'a Report),
+    /// A backlight device appeared, such as when docking.
+    DeviceAdded { device: &'a str },
+    /// A backlight device went away.
+    DeviceRemoved { device: &'a str },
+    /// A device's range changed, which some drivers do on mode changes.
+    MaxChanged { device: &'a str, old: u32, new: u32 },
+    /// A device's `bl_power` changed. 0 means on; anything else is one of the
+    /// kernel's blanking levels, 4 being powered down.
+    Power { device: &'a str, bl_power: u32 },
+}
+
+impl fmt::Display for Event<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Status(report) => write!(f, "{report}"),
+            Event::DeviceAdded { device } => {
+                writeln!(f, "event:      {device} added")
+            }
+            Event::DeviceRemoved { device } => {
+                writeln!(f, "event:      {device} removed")
+            }
+            Event::MaxChanged { device, old, new } => {
+                writeln!(f, "event:      {device} max changed {old} -> {new}")
+            }
+            Event::Power { device, bl_power } => {
+                let state = if *bl_power == 0 { "on" } else { "off" };
+                writeln!(f, "event:      {device} powered {state} ({bl_power})")
+            }
+        }
+    }
+}
+
+/// The parts of a device that `Event`s are about.
+#[derive(Debug, PartialEq)]
+struct Lifecycle {
+    /// Raw maximum, if readable.
+    max: Option<u32>,
+    /// `bl_power`, if the driver has it.
+    bl_power: Option<u32>,
+}
+
+/// Looks at every backlight device in sysfs.
+fn lifecycles() -> BTreeMap<String, Lifecycle> {
+    let read = |name: &str, attr: &str| {
+        let path = brightr::backlight_dir().join(name).join(attr);
+        fs::read_to_string(path).ok()?.trim().parse().ok()
+    };
+    device_names()
+        .into_iter()
+        .map(|name| {
+            let state = Lifecycle {
+                max: read(&name, "max_brightness"),
+                bl_power: read(&name, "bl_power"),
+            };
+            (name, state)
+        })
+        .collect()
+}
+
+/// Works out the events that take the devices from `old` to `new`.
+fn events<'a>(
+    old: &'a BTreeMap<String, Lifecycle>,
+    new: &'a BTreeMap<String, Lifecycle>,
+) -> Vec<Event<'a>> {
+    let mut events = vec![];
+    for device in old.keys().filter(|name| !new.contains_key(*name)) {
+        events.push(Event::DeviceRemoved { device });
+    }
+    for (device, now) in new {
+        let Some(was) = old.get(device) else {
+            events.push(Event::DeviceAdded { device });
+            continue;
+        };
+        if let (Some(old), Some(new)) = (was.max, now.max) {
+            if old != new {
+                events.push(Event::MaxChanged { device, old, new });
+            }
+        }
+        if let Some(bl_power) = now.bl_power {
+            if was.bl_power != now.bl_power {
+                events.push(Event::Power { device, bl_power });
+            }
+        }
+    }
+    events
+}
+
+/// Which logind session we're in.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+struct SessionReport {
+    id: String,
+    seat: Option<String>,
+    uid: u32,
+    user: String,
+}
+
+impl SessionReport {
+    /// Asks logind about our session, if there is one.
+    fn find() -> Option<Self> {
+        let session = brightr::connect_to_session().ok()?;
+        let info = brightr::session_info(&session).ok()?;
+        Some(SessionReport {
+            id: info.id,
+            seat: info.seat,
+            uid: info.uid,
+            user: info.user,
+        })
+    }
+}
+
+/// What a single policy is up to.
+#[derive(Debug, PartialEq, Serialize)]
+struct PolicyReport {
+    name: &'static str,
+    /// Cap requested by the policy, as written in the config.
+    cap: Option<String>,
+    /// Cap requested by the policy, in raw units.
+    cap_raw: Option<u32>,
+    /// Why the policy couldn't decide, if it couldn't.
+    error: Option<String>,
+}
+
+impl Report {
+    fn new(
+        bl: &Backlight,
+        current: u32,
+        e: f64,
+        backend: Option<&'static str>,
+        session: Option<SessionReport>,
+        verdicts: &[Verdict],
+    ) -> Self {
+        let cap_to_raw = |cap: crate::Value| cap.to_raw(bl, e, Unit::Percent);
+        let (ceiling, capped_by) =
+            policy::ceiling(verdicts, bl.max, cap_to_raw);
+        Report {
+            device: bl.name.clone(),
+            kind: bl.kind().ok(),
+            driver: bl.driver(),
+            connector: bl.connector(),
+            raw: current,
+            max: bl.max,
+            percent: bl.percent_from_raw(current, e),
+            backend,
+            session,
+            ceiling: capped_by.map(|_| ceiling),
+            capped_by,
+            policies: verdicts
+                .iter()
+                .map(|v| {
+                    let (cap, error) = match &v.cap {
+                        Ok(cap) => (*cap, None),
+                        Err(e) => (None, Some(e.to_string())),
+                    };
+                    PolicyReport {
+                        name: v.policy,
+                        cap: cap.map(|c| c.to_string()),
+                        cap_raw: cap.map(cap_to_raw),
+                        error,
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.kind, &self.driver) {
+            (Some(kind), Some(driver)) => {
+                writeln!(f, "device:     {} ({kind}, {driver})", self.device)?
+            }
+            (Some(about), None) | (None, Some(about)) => {
+                writeln!(f, "device:     {} ({about})", self.device)?
+            }
+            (None, None) => writeln!(f, "device:     {}", self.device)?,
+        }
+        if let Some(connector) = &self.connector {
+            writeln!(f, "connector:  {connector}")?;
+        }
+        if self.max == 1 {
+            let state = if self.raw == 0 { "off" } else { "on" };
+            writeln!(f, "brightness: {state} (on/off device)")?;
+        } else {
+            writeln!(
+                f,
+                "brightness: {}% ({}/{} raw)",
+                self.percent, self.raw, self.max
+            )?;
+        }
+        writeln!(f, "backend:    {}", self.backend.unwrap_or("none working"))?;
+        if let Some(s) = &self.session {
+            let seat = s.seat.as_deref().unwrap_or("no seat");
+            writeln!(
+                f,
+                "session:    {} on {seat}, user {} ({})",
+                s.id, s.user, s.uid
+            )?;
+        }
+        match (self.ceiling, self.capped_by) {
+            (Some(ceiling), Some(policy)) => {
+                writeln!(f, "ceiling:    {ceiling} raw, set by {policy}")?
+            }
+            _ => writeln!(f, "ceiling:    none")?,
+        }
+
+        if self.policies.is_empty() {
+            return Ok(());
+        }
+        writeln!(f, "policies:")?;
+        for p in &self.policies {
+            match (&p.cap, p.cap_raw, &p.error) {
+                (_, _, Some(e)) => writeln!(f, "  {:<12} error: {e}", p.name)?,
+                (Some(cap), Some(raw), _) => writeln!(
+                    f,
+                    "  {:<12} capping at {cap} ({raw} raw)",
+                    p.name
+                )?,
+                _ => writeln!(f, "  {:<12} inactive", p.name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Waits between checks for changes, for `status --watch`.
+#[derive(Debug)]
+struct Waiter {
+    /// The longest to wait.
+    interval: Duration,
+    /// Watches the device in use, named alongside, so that brightness changes
+    /// are reported right away rather than at the next check.
+    #[cfg(all(feature = "watch", target_os = "linux"))]
+    watcher: Option<(String, brightr::watch::Watcher)>,
+}
+
+impl Waiter {
+    fn new(interval: Duration) -> Self {
+        Waiter {
+            interval,
+            #[cfg(all(feature = "watch", target_os = "linux"))]
+            watcher: None,
+        }
+    }
+
+    /// Waits for `interval` to pass, or for `bl`'s brightness to change, if
+    /// that can be watched; otherwise, this just sleeps.
+    #[cfg_attr(
+        not(all(feature = "watch", target_os = "linux")),
+        allow(unused_variables)
+    )]
+    fn wait(&mut self, bl: &Backlight) {
+        #[cfg(all(feature = "watch", target_os = "linux"))]
+        {
+            use rustix::event::{poll, PollFd, PollFlags, Timespec};
+
+            // Start over if the device changed, or went away and came back.
+            let stale = self.watcher.as_ref().is_none_or(|(name, watcher)| {
+                *name != bl.name || watcher.is_gone()
+            });
+            if stale {
+                self.watcher = match bl.watch() {
+                    Ok(watcher) => Some((bl.name.clone(), watcher)),
+                    Err(e) => {
+                        debug!("can't watch {}, polling: {e}", bl.name);
+                        None
+                    }
+                };
+            }
+            if let Some((_, watcher)) = &mut self.watcher {
+                let timeout = Timespec::try_from(self.interval)
+                    .expect("interval in milliseconds should fit");
+                let mut fds = [PollFd::new(&*watcher, PollFlags::IN)];
+                // Being interrupted just means checking early.
+                let _ = poll(&mut fds, Some(&timeout));
+                // Take the news, so that the next wait doesn't end at once.
+                // We read the device again anyway.
+                if let Err(e) = watcher.check() {
+                    debug!("can't watch {}: {e}", bl.name);
+                    self.watcher = None;
+                }
+                return;
+            }
+        }
+        thread::sleep(self.interval);
+    }
+}
+
+/// Runs the `status` command, starting from backlight `bl` at raw setting
+/// `current`. If `watch` is given, this checks that often for changes, calling
+/// `reread` to refresh the device, and reports again whenever something
+/// changes; with the `watch` feature, brightness changes are reported as soon
+/// as they happen. It then never returns unless there's an error.
+/// `reread` is only called when devices have come, gone or changed, since
+/// that's all that can change which device to use; otherwise, the device
+/// already found is read again.
+/// Watching also reports devices coming and going, and changes to their range
+/// and power state, as events.
+pub fn run(
+    args: &Brightr,
+    config: &Config,
+    watch: Option<Duration>,
+    json: bool,
+    mut bl: Backlight,
+    mut current: u32,
+    mut reread: impl FnMut() -> anyhow::Result<(Backlight, u32)>,
+) -> anyhow::Result<()> {
+    let policies = if args.override_cap {
+        vec![]
+    } else {
+        policy::from_config(config)
+    };
+    // There's no change in mind, so the policies only get to see where the
+    // device is.
+    let evaluate = |bl: &Backlight, current| {
+        let context = policy::Context {
+            backlight: bl,
+            current,
+            target: None,
+        };
+        policy::evaluate(&policies, &context)
+    };
+    let mut verdicts = evaluate(&bl, current);
+    let mut evaluated = Instant::now();
+    // Finding the backend can mean connecting to DBus, so it's only worked out
+    // again when the device changes.
+    let mut backend: Option<(String, Option<&'static str>)> = None;
+    let mut last = None;
+    let mut devices = lifecycles();
+    let mut waiter = watch.map(Waiter::new);
+    // Sessions don't change under us, so one look is enough. The keyboard
+    // backlight goes through UPower, which doesn't care about sessions.
+    let session = if args.keyboard || args.no_dbus {
+        None
+    } else {
+        SessionReport::find()
+    };
+    loop {
+        let stale = backend.as_ref().is_none_or(|(name, _)| *name != bl.name);
+        if stale {
+            let found = if args.keyboard {
+                Some("upower")
+            } else {
+                backend::active(&args.backends(config), &bl).map(Backend::name)
+            };
+            backend = Some((bl.name.clone(), found));
+        }
+        let report = Report::new(
+            &bl,
+            current,
+            args.exponent,
+            backend.as_ref().and_then(|(_, backend)| *backend),
+            session.clone(),
+            &verdicts,
+        );
+        if last.as_ref() != Some(&report) {
+            if json && watch.is_some() {
+                println!("{}", serde_json::to_string(&Event::Status(&report))?);
+            } else if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                // Separate successive reports with a blank line.
+                if last.is_some() {
+                    println!();
+                }
+                print!("{report}");
+            }
+            last = Some(report);
+        }
+
+        let Some(waiter) = &mut waiter else {
+            return Ok(());
+        };
+        waiter.wait(&bl);
+
+        // The keyboard backlight isn't in sysfs, as far as we know, so it's
+        // always looked for again.
+        let mut changed = true;
+        if !args.keyboard {
+            let now = lifecycles();
+            for event in events(&devices, &now) {
+                if json {
+                    println!("{}", serde_json::to_string(&event)?);
+                } else {
+                    print!("{event}");
+                }
+            }
+            changed = now != devices;
+            devices = now;
+        }
+        // Looking for the device again would repeat any complaints about
+        // devices being skipped, so only do it when something's changed.
+        // The device may have gone away, in which case we've just said so,
+        // and we wait for it to come back.
+        if changed {
+            match reread() {
+                Ok(found) => (bl, current) = found,
+                Err(e) => debug!("can't reread device: {e}"),
+            }
+        } else {
+            match bl.read_current() {
+                Ok(now) => current = now,
+                Err(e) => debug!("can't reread {}: {e}", bl.name),
+            }
+        }
+
+        if changed || evaluated.elapsed() >= POLICY_INTERVAL {
+            verdicts = evaluate(&bl, current);
+            evaluated = Instant::now();
+        }
+    }
+}
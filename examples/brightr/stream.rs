@@ -0,0 +1,62 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The `stream` command, which applies a stream of values from stdin.
+
+use crate::Value;
+use std::{
+    io::{self, Read},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Runs the `stream` command, reading values from stdin and passing them to
+/// `apply`, no more often than every `interval`. Values that arrive faster
+/// than that are skipped in favor of the latest.
+pub fn run(
+    interval: Duration,
+    mut apply: impl FnMut(Value) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    // Reading happens on its own thread, so that we can tell when we've
+    // fallen behind and skip ahead.
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut word = String::new();
+        let send = |word: &mut String| {
+            if word.is_empty() {
+                return;
+            }
+            match word.parse::<Value>() {
+                // If nobody's listening, we're on our way out anyway.
+                Ok(value) => drop(tx.send(value)),
+                Err(e) => eprintln!("ignoring bad value `{word}`: {e}"),
+            }
+            word.clear();
+        };
+        for byte in io::stdin().lock().bytes() {
+            let Ok(byte) = byte else {
+                break;
+            };
+            if byte.is_ascii_whitespace() {
+                send(&mut word);
+            } else {
+                word.push(char::from(byte));
+            }
+        }
+        send(&mut word);
+    });
+
+    while let Ok(mut value) = rx.recv() {
+        while let Ok(newer) = rx.try_recv() {
+            value = newer;
+        }
+        let start = Instant::now();
+        apply(value)?;
+        if let Some(rest) = interval.checked_sub(start.elapsed()) {
+            thread::sleep(rest);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,79 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The `test-device` command, which checks that a device really responds to
+//! changes.
+//!
+//! When the brightness doesn't change, it's either because we weren't allowed
+//! to change it, or because the driver accepted the change and then ignored
+//! it. Those need very different fixes, so this tells them apart.
+
+use crate::backend::{self, Backend};
+use anyhow::{bail, Context};
+use brightr::Backlight;
+use std::{thread, time::Duration};
+
+/// How long to give the hardware to catch up before reading it back.
+pub const SETTLE_TIME: Duration = Duration::from_millis(100);
+
+/// Runs the `test-device` command on `bl`, currently at raw setting `current`,
+/// using the first backend in `chain` that works.
+pub fn run(
+    chain: &[Backend],
+    bl: &Backlight,
+    current: u32,
+) -> anyhow::Result<()> {
+    println!("device:   {}", bl.name);
+    // Without actual_brightness, we'd only read back what we wrote.
+    let Some(before) = bl.try_read_actual()? else {
+        bail!(
+            "{} has no actual_brightness, so there's no way to tell whether \
+             the hardware follows a change",
+            bl.name
+        );
+    };
+    // Move by a whole real level, since smaller changes can't be seen.
+    let mut nudged = bl.quantize(current.saturating_add(bl.step));
+    if nudged == current {
+        nudged = bl.quantize(current.saturating_sub(bl.step));
+    }
+    if nudged == current {
+        bail!("device has only one setting, so there's nothing to test");
+    }
+
+    let used = backend::set(chain, bl, nudged).context(
+        "can't change the brightness, so this is a permission or \
+         configuration problem rather than a driver one",
+    )?;
+    println!("write:    set {current} -> {nudged} using {}", used.name());
+
+    thread::sleep(SETTLE_TIME);
+    let actual = bl.try_read_actual();
+
+    // Put things back before reporting, whatever happened.
+    let restored = used.set(bl, current);
+
+    // Comparing with the reading from before, rather than with what we wrote,
+    // copes with drivers that report on a different scale.
+    let result = match actual {
+        Ok(Some(actual)) if actual != before => {
+            println!("readback: {actual}, so the device responds");
+            Ok(())
+        }
+        Ok(Some(actual)) => {
+            println!("readback: {actual}");
+            Err(anyhow::anyhow!(
+                "the write was accepted, but the device didn't change; the \
+                 driver seems to be ignoring it"
+            ))
+        }
+        Ok(None) => Err(anyhow::anyhow!("actual_brightness went away")),
+        Err(e) => Err(e).context("can't read the device back"),
+    };
+    match restored {
+        Ok(()) => println!("restore:  set back to {current}"),
+        Err(e) => eprintln!("can't restore the original setting: {e:#}"),
+    }
+    result
+}
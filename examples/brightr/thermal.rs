@@ -0,0 +1,73 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Temperature readings from the kernel's thermal zones.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Finds the highest temperature, in degrees Celsius, currently reported by
+/// any thermal zone in `/sys/class/thermal`. If `zone_type` is given, only
+/// zones with that type (e.g. `x86_pkg_temp`) are considered.
+///
+/// Returns `None` if there are no matching zones.
+pub fn hottest(zone_type: Option<&str>) -> io::Result<Option<f64>> {
+    let mut hottest = None;
+    for dirent in fs::read_dir(dir())? {
+        let path = dirent?.path();
+        if !is_zone(&path) {
+            continue;
+        }
+        if let Some(wanted) = zone_type {
+            if read_attr(&path, "type")? != wanted {
+                continue;
+            }
+        }
+        // Zones can fail to read for boring reasons, like the sensor being
+        // powered down, so we skip any that do rather than giving up.
+        let Some(temp) = read_attr(&path, "temp")
+            .ok()
+            .and_then(|t| t.parse::<i64>().ok())
+        else {
+            continue;
+        };
+        // The kernel reports millidegrees.
+        let temp = temp as f64 / 1000.;
+        hottest = Some(hottest.unwrap_or(temp).max(temp));
+    }
+    Ok(hottest)
+}
+
+/// Lists the types of all the thermal zones on this machine.
+pub fn zone_types() -> io::Result<Vec<String>> {
+    let mut types = vec![];
+    for dirent in fs::read_dir(dir())? {
+        let path = dirent?.path();
+        if is_zone(&path) {
+            types.push(read_attr(&path, "type")?);
+        }
+    }
+    Ok(types)
+}
+
+/// Returns the directory where the kernel lists thermal zones, which honors
+/// `BRIGHTR_SYSFS` like the backlight devices do.
+fn dir() -> PathBuf {
+    brightr::sysfs_root().join("class/thermal")
+}
+
+/// Checks whether `path` is a thermal zone. Cooling devices live in the same
+/// directory, and we want to skip them.
+fn is_zone(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.starts_with("thermal_zone"))
+}
+
+/// Reads a single sysfs attribute of a thermal zone, minus trailing newline.
+fn read_attr(zone: &Path, attr: &str) -> io::Result<String> {
+    Ok(fs::read_to_string(zone.join(attr))?.trim().to_owned())
+}
@@ -0,0 +1,236 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Working out where a change to the brightness should land, so that every
+//! program using the library agrees on what "up 10%" means.
+
+use crate::{Backlight, Brightness, Error};
+
+/// A change to a backlight's brightness.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Adjustment {
+    /// Go to this brightness.
+    Set(Brightness),
+    /// Go up by this amount.
+    Increase(Brightness),
+    /// Go down by this amount.
+    Decrease(Brightness),
+}
+
+/// What `Adjustment::apply_with` does when a step can't go any further.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Clamp {
+    /// Stop at the limit, and stay there on later steps.
+    #[default]
+    Saturate,
+    /// Stop at the limit, but fail with `Error::AtLimit` on a step that
+    /// starts there, so callers can tell the user nothing happened.
+    Strict,
+}
+
+/// Settings for `Adjustment::apply_with`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AdjustOptions {
+    /// Exponent for percentages and fractions, as taken by
+    /// `Backlight::raw_from_fraction`. The default, 1, is linear.
+    pub exponent: f64,
+    /// Lowest raw value to land on, so the display never goes fully dark.
+    /// If this is above `max`, `max` wins.
+    pub min: u32,
+    /// Highest raw value to land on, if lower than the backlight's own
+    /// maximum.
+    pub max: Option<u32>,
+    /// What to do with steps that start at a limit.
+    pub clamp: Clamp,
+}
+
+impl Default for AdjustOptions {
+    fn default() -> Self {
+        Self {
+            exponent: 1.,
+            min: 0,
+            max: None,
+            clamp: Clamp::Saturate,
+        }
+    }
+}
+
+impl Adjustment {
+    /// Works out the raw value this change lands on for `backlight`, starting
+    /// from raw value `current`, with the default options: linear, with the
+    /// whole of the device's range allowed.
+    pub fn apply(self, backlight: &Backlight, current: u32) -> u32 {
+        let options = AdjustOptions::default();
+        // The default options never fail.
+        self.apply_with(backlight, current, &options)
+            .unwrap_or(current)
+    }
+
+    /// Works out the raw value this change lands on for `backlight`, starting
+    /// from raw value `current`.
+    ///
+    /// Percentage and fraction steps move along the curve given by
+    /// `options.exponent`, rather than by raw values. Steps always move by at
+    /// least one of the hardware's real levels (see `Backlight::step`) unless
+    /// their amount is zero, so that rounding can't leave them going nowhere.
+    /// The result is then clamped between `options.min` and the top of the
+    /// allowed range.
+    pub fn apply_with(
+        self,
+        backlight: &Backlight,
+        current: u32,
+        options: &AdjustOptions,
+    ) -> Result<u32, Error> {
+        let bl = backlight;
+        let e = options.exponent;
+        let ceiling = options.max.map_or(bl.max, |max| max.min(bl.max));
+        let floor = options.min.min(ceiling);
+
+        let requested = match self {
+            Adjustment::Set(Brightness::Raw(raw)) => raw,
+            Adjustment::Set(Brightness::Percent(pct)) => {
+                bl.raw_from_fraction(pct / 100., e)
+            }
+            Adjustment::Set(Brightness::Fraction(fraction)) => {
+                bl.raw_from_fraction(fraction, e)
+            }
+            Adjustment::Increase(by) | Adjustment::Decrease(by) => {
+                let down = matches!(self, Adjustment::Decrease(_));
+                if options.clamp == Clamp::Strict {
+                    if down && current <= floor {
+                        return Err(Error::AtLimit(floor));
+                    }
+                    if !down && current >= ceiling {
+                        return Err(Error::AtLimit(ceiling));
+                    }
+                }
+                // Moves `fraction` of the way along the curve.
+                let along_curve = |fraction: f64| {
+                    let from = bl.fraction_from_raw(current, e);
+                    let to = if down {
+                        from - fraction
+                    } else {
+                        from + fraction
+                    };
+                    bl.raw_from_fraction(to, e)
+                };
+                let requested = match by {
+                    Brightness::Raw(by) if down => current.saturating_sub(by),
+                    Brightness::Raw(by) => current.saturating_add(by),
+                    Brightness::Percent(pct) => along_curve(pct / 100.),
+                    Brightness::Fraction(fraction) => along_curve(fraction),
+                };
+                let zero = match by {
+                    Brightness::Raw(by) => by == 0,
+                    Brightness::Percent(by) | Brightness::Fraction(by) => {
+                        by == 0.
+                    }
+                };
+                if zero {
+                    current
+                } else {
+                    escape(bl, current, requested, down)
+                }
+            }
+        };
+        Ok(requested.clamp(floor, ceiling))
+    }
+}
+
+/// Makes sure a step from `current` to `requested` on `bl` moves at least one
+/// real level, in the direction given by `down`.
+fn escape(bl: &Backlight, current: u32, requested: u32, down: bool) -> u32 {
+    let requested = bl.quantize(requested);
+    if down {
+        requested.min(bl.quantize(current.saturating_sub(bl.step)))
+    } else {
+        requested.max(bl.quantize(current.saturating_add(bl.step)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BrightnessFile, Subsystem};
+
+    /// A device with raw range 0 to 1000, moving in `step`s.
+    fn device(step: u32) -> Backlight {
+        Backlight {
+            name: "test".to_string(),
+            subsystem: Subsystem::Backlight,
+            max: 1000,
+            step,
+            source: BrightnessFile::Brightness,
+        }
+    }
+
+    /// Options allowing raw values `min` to `max`, with `clamp`.
+    fn range(min: u32, max: u32, clamp: Clamp) -> AdjustOptions {
+        AdjustOptions {
+            min,
+            max: Some(max),
+            clamp,
+            ..AdjustOptions::default()
+        }
+    }
+
+    #[test]
+    fn clamps_to_range() {
+        let bl = device(1);
+        let options = range(100, 800, Clamp::Saturate);
+        let set = |raw| Adjustment::Set(Brightness::Raw(raw));
+        assert_eq!(set(900).apply_with(&bl, 500, &options).unwrap(), 800);
+        assert_eq!(set(50).apply_with(&bl, 500, &options).unwrap(), 100);
+        let up = Adjustment::Increase(Brightness::Percent(50.));
+        assert_eq!(up.apply_with(&bl, 500, &options).unwrap(), 800);
+        let down = Adjustment::Decrease(Brightness::Raw(u32::MAX));
+        assert_eq!(down.apply_with(&bl, 500, &options).unwrap(), 100);
+        // Without options, the device's own range is the limit.
+        assert_eq!(set(5000).apply(&bl, 500), 1000);
+        // The cap wins over the floor if they cross.
+        let crossed = range(900, 600, Clamp::Saturate);
+        assert_eq!(set(0).apply_with(&bl, 500, &crossed).unwrap(), 600);
+        let max = AdjustOptions {
+            max: Some(5000),
+            ..AdjustOptions::default()
+        };
+        assert_eq!(set(5000).apply_with(&bl, 500, &max).unwrap(), 1000);
+    }
+
+    #[test]
+    fn strict_steps_fail_at_limits() {
+        let bl = device(1);
+        let up = Adjustment::Increase(Brightness::Raw(10));
+        let down = Adjustment::Decrease(Brightness::Raw(10));
+        let strict = range(100, 800, Clamp::Strict);
+        assert!(matches!(
+            up.apply_with(&bl, 800, &strict),
+            Err(Error::AtLimit(800))
+        ));
+        assert!(matches!(
+            down.apply_with(&bl, 100, &strict),
+            Err(Error::AtLimit(100))
+        ));
+        // Steps that only reach the limit are fine, as are sets.
+        assert_eq!(up.apply_with(&bl, 795, &strict).unwrap(), 800);
+        let set = Adjustment::Set(Brightness::Raw(900));
+        assert_eq!(set.apply_with(&bl, 800, &strict).unwrap(), 800);
+        // Saturating just stays put.
+        let saturate = range(100, 800, Clamp::Saturate);
+        assert_eq!(up.apply_with(&bl, 800, &saturate).unwrap(), 800);
+        assert_eq!(down.apply_with(&bl, 100, &saturate).unwrap(), 100);
+    }
+
+    #[test]
+    fn steps_move_a_real_level() {
+        let bl = device(250);
+        let up = Adjustment::Increase(Brightness::Raw(1));
+        let down = Adjustment::Decrease(Brightness::Raw(1));
+        assert_eq!(up.apply(&bl, 250), 500);
+        assert_eq!(down.apply(&bl, 250), 0);
+        // A step of nothing goes nowhere.
+        let zero = Adjustment::Increase(Brightness::Percent(0.));
+        assert_eq!(zero.apply(&bl, 250), 250);
+    }
+}
@@ -0,0 +1,80 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! A handle on one backlight that stays connected to logind, for programs
+//! that adjust the brightness again and again, like hotkey daemons.
+
+use crate::{
+    adjust::Adjustment, connect_to_session, find_first_backlight,
+    set_brightness_checked, Backlight, Brightness, Error, SessionProxyBlocking,
+};
+
+/// Controls one backlight through logind, keeping the connection open
+/// between changes, rather than making a new one each time like
+/// `connect_and_set_brightness`.
+///
+/// Values read back are raw. Changes are clamped to the device's range and
+/// rounded to levels the hardware can really show, as by
+/// `set_brightness_checked`.
+#[derive(Clone, Debug)]
+pub struct BrightnessController {
+    session: SessionProxyBlocking<'static>,
+    backlight: Backlight,
+}
+
+impl BrightnessController {
+    /// Connects to logind to control the first backlight found by
+    /// `find_first_backlight`.
+    pub fn new() -> Result<Self, Error> {
+        let (backlight, _) = find_first_backlight()?;
+        Self::for_backlight(backlight)
+    }
+
+    /// Connects to logind to control `backlight`.
+    pub fn for_backlight(backlight: Backlight) -> Result<Self, Error> {
+        Ok(Self {
+            session: connect_to_session()?,
+            backlight,
+        })
+    }
+
+    /// Returns the backlight being controlled.
+    pub fn backlight(&self) -> &Backlight {
+        &self.backlight
+    }
+
+    /// Reads the current raw setting. This comes from sysfs each time, so it
+    /// reflects changes made by other programs.
+    pub fn get(&self) -> Result<u32, Error> {
+        self.backlight.read_current()
+    }
+
+    /// Sets the brightness to `value`, which may be a `Brightness` or a raw
+    /// `u32`, and returns the raw value actually written.
+    pub fn set(&self, value: impl Into<Brightness>) -> Result<u32, Error> {
+        set_brightness_checked(&self.session, &self.backlight, value)
+    }
+
+    /// Raises the brightness by `by` raw units, and returns the value
+    /// actually written. Unless `by` is 0, this always moves by at least one
+    /// of the hardware's real levels (see `Backlight::step`), so small steps
+    /// can't be lost to rounding.
+    pub fn up(&self, by: u32) -> Result<u32, Error> {
+        self.adjust(Adjustment::Increase(Brightness::Raw(by)))
+    }
+
+    /// Lowers the brightness by `by` raw units, and returns the value
+    /// actually written. Like `up`, this always moves by at least one real
+    /// level unless `by` is 0.
+    pub fn down(&self, by: u32) -> Result<u32, Error> {
+        self.adjust(Adjustment::Decrease(Brightness::Raw(by)))
+    }
+
+    /// Makes any change to the brightness, starting from its current value,
+    /// and returns the raw value actually written. See `Adjustment::apply`.
+    pub fn adjust(&self, adjustment: Adjustment) -> Result<u32, Error> {
+        let current = self.get()?;
+        self.set(adjustment.apply(&self.backlight, current))
+    }
+}
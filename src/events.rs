@@ -0,0 +1,106 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Watching a backlight device for changes, as an async `Stream`.
+//!
+//! This is only available with the `async` feature. The stream doesn't depend
+//! on any particular async runtime, so it can be combined with other event
+//! sources using whatever `select!` the application already has.
+//!
+//! Not every driver tells anyone when the brightness changes, so we find
+//! changes by checking the device regularly.
+
+use crate::{backlight_dir, read_backlight_settings, Error};
+use async_io::Timer;
+use futures_lite::{stream, Stream};
+use std::{collections::VecDeque, time::Duration};
+
+/// Something that happened to a backlight device.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Event {
+    /// The device's setting changed to this raw value, whether because of
+    /// us, another program, or the firmware.
+    Brightness(u32),
+    /// The device's highest raw value changed to this. Some drivers do this
+    /// when the display mode changes.
+    Max(u32),
+    /// The device went away, for instance because a dock was unplugged.
+    Removed,
+    /// The device appeared, or came back after being removed. In the latter
+    /// case, this is followed by events for any settings that differ from
+    /// before.
+    Added,
+}
+
+/// Watches the backlight device called `name`, checking it every `interval`.
+///
+/// The stream yields an `Event` for each change found, starting from the
+/// state of the device when this is called, and never ends. The device doesn't
+/// need to exist yet. Errors reading a device that still exists are passed on,
+/// and watching continues.
+pub fn watch(
+    name: impl Into<String>,
+    interval: Duration,
+) -> impl Stream<Item = Result<Event, Error>> {
+    let path = backlight_dir().join(name.into());
+    let last = read_backlight_settings(&path).ok();
+    let state = Watcher {
+        present: last.is_some(),
+        last,
+        pending: VecDeque::new(),
+    };
+    stream::unfold(state, move |mut state| {
+        let path = path.clone();
+        async move {
+            while state.pending.is_empty() {
+                Timer::after(interval).await;
+                let now = match read_backlight_settings(&path) {
+                    Ok(now) => Some(now),
+                    Err(_) if !path.exists() => None,
+                    Err(e) => return Some((Err(e), state)),
+                };
+                state.update(now);
+            }
+            let event = state.pending.pop_front()?;
+            Some((Ok(event), state))
+        }
+    })
+}
+
+/// What `watch` knows about its device.
+struct Watcher {
+    /// Whether the device was there last time we looked.
+    present: bool,
+    /// The last `(current, max)` setting seen, if we've ever seen one. This
+    /// survives the device going away, so we can tell what changed when it
+    /// comes back.
+    last: Option<(u32, u32)>,
+    /// Events found but not yet yielded.
+    pending: VecDeque<Event>,
+}
+
+impl Watcher {
+    /// Records the device's setting as `now`, queueing events for any changes.
+    fn update(&mut self, now: Option<(u32, u32)>) {
+        match (self.present, now) {
+            (true, None) => self.pending.push_back(Event::Removed),
+            (false, Some(_)) => self.pending.push_back(Event::Added),
+            _ => (),
+        }
+        self.present = now.is_some();
+        let Some((current, max)) = now else {
+            return;
+        };
+        if let Some((old_current, old_max)) = self.last {
+            if max != old_max {
+                self.pending.push_back(Event::Max(max));
+            }
+            if current != old_current {
+                self.pending.push_back(Event::Brightness(current));
+            }
+        }
+        self.last = now;
+    }
+}
@@ -0,0 +1,629 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Fading smoothly from one brightness to another, rather than jumping,
+//! which is jarring, particularly in the dark.
+
+use crate::{
+    check_range, set_brightness, Backlight, Brightness, Error,
+    SessionProxyBlocking,
+};
+use std::{
+    fmt,
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Steps per second for `fade_to`. This is smooth to the eye without keeping
+/// logind busy.
+pub const DEFAULT_RATE: u32 = 60;
+
+/// How a fade's progress is spread over its duration.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Easing {
+    /// At an even pace throughout.
+    Linear,
+    /// Starting slowly and speeding up.
+    EaseIn,
+    /// Starting quickly and slowing down at the end.
+    EaseOut,
+    /// Starting and finishing slowly, which looks the most natural.
+    #[default]
+    EaseInOut,
+}
+
+impl Easing {
+    /// Maps time `t`, from 0 at the start of a fade to 1 at its end, to how
+    /// far through the change the fade should be, also from 0 to 1.
+    pub fn apply(self, t: f64) -> f64 {
+        let t = t.clamp(0., 1.);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2. - t),
+            Easing::EaseInOut => t * t * (3. - 2. * t),
+        }
+    }
+}
+
+/// Fades `backlight` from its current setting to `target` over `duration`,
+/// through `session`, taking `DEFAULT_RATE` steps a second. This blocks until
+/// the fade is done, and returns the raw value it finished at.
+///
+/// Returns `Error::OutOfRange` if `target` is above `backlight.max`.
+pub fn fade_to(
+    session: &SessionProxyBlocking<'_>,
+    backlight: &Backlight,
+    target: impl Into<Brightness>,
+    duration: Duration,
+    easing: Easing,
+) -> Result<u32, Error> {
+    fade_to_with_rate(
+        session,
+        backlight,
+        target,
+        duration,
+        easing,
+        DEFAULT_RATE,
+    )
+}
+
+/// Like `fade_to`, but taking `rate` steps a second rather than
+/// `DEFAULT_RATE`.
+pub fn fade_to_with_rate(
+    session: &SessionProxyBlocking<'_>,
+    backlight: &Backlight,
+    target: impl Into<Brightness>,
+    duration: Duration,
+    easing: Easing,
+    rate: u32,
+) -> Result<u32, Error> {
+    fade_to_with_progress(
+        session,
+        backlight,
+        target,
+        duration,
+        easing,
+        rate,
+        |_| (),
+    )
+}
+
+/// Like `fade_to_with_rate`, but calling `on_step` with each raw value once
+/// it's been written, for callers that show the fade's progress, like an OSD.
+pub fn fade_to_with_progress(
+    session: &SessionProxyBlocking<'_>,
+    backlight: &Backlight,
+    target: impl Into<Brightness>,
+    duration: Duration,
+    easing: Easing,
+    rate: u32,
+    mut on_step: impl FnMut(u32),
+) -> Result<u32, Error> {
+    let target = target.into().to_raw(backlight);
+    check_range(target, backlight.max)?;
+    let from = backlight.read_current()?;
+    run(from, target, duration, easing, rate, |value| {
+        set_brightness(session, backlight, value)?;
+        on_step(value);
+        Ok::<_, Error>(())
+    })?;
+    Ok(target)
+}
+
+/// Runs a fade from raw value `from` to `to` over `duration`, taking `rate`
+/// steps a second, by calling `set` with each new value when it's due. Steps
+/// that wouldn't change the value are skipped, and the last step is always
+/// exactly `to`. The first error from `set` ends the fade.
+///
+/// This is the heart of `fade_to`, for callers with their own ways of
+/// setting the brightness.
+pub fn run<E>(
+    from: u32,
+    to: u32,
+    duration: Duration,
+    easing: Easing,
+    rate: u32,
+    mut set: impl FnMut(u32) -> Result<(), E>,
+) -> Result<(), E> {
+    let plan = Plan::new(from, to, duration, easing, rate);
+    let mut last = from;
+    for (value, wait) in plan.steps() {
+        if value != last {
+            if let Some(wait) = wait() {
+                thread::sleep(wait);
+            }
+            set(value)?;
+            last = value;
+        }
+    }
+    Ok(())
+}
+
+/// A fade running on a thread of its own, which can be stopped, or sent
+/// somewhere else, part way through. This suits programs like hotkey
+/// daemons, where the latest keypress should win, rather than queueing up
+/// behind fades from earlier ones.
+///
+/// Dropping the handle leaves the fade to finish by itself.
+pub struct FadeHandle {
+    shared: Arc<Shared>,
+    /// Writes each step; normally `set_brightness` through a session.
+    set: Setter,
+    backlight: Backlight,
+    duration: Duration,
+    easing: Easing,
+    thread: Option<JoinHandle<Result<u32, Error>>>,
+}
+
+/// How a `FadeHandle`'s thread writes each step.
+type Setter = Arc<dyn Fn(u32) -> Result<(), Error> + Send + Sync>;
+
+impl fmt::Debug for FadeHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FadeHandle")
+            .field("shared", &self.shared)
+            .field("backlight", &self.backlight)
+            .field("duration", &self.duration)
+            .field("easing", &self.easing)
+            .field("thread", &self.thread)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Makes a `Setter` that writes each step with `set`, and then, if that
+/// worked, tells `on_step`.
+fn with_progress(
+    set: impl Fn(u32) -> Result<(), Error> + Send + Sync + 'static,
+    on_step: impl FnMut(u32) + Send + 'static,
+) -> Setter {
+    // Only the fade's thread calls it, but `Setter` has to be `Sync`.
+    let on_step = Mutex::new(on_step);
+    Arc::new(move |value| {
+        set(value)?;
+        (on_step.lock().unwrap_or_else(PoisonError::into_inner))(value);
+        Ok(())
+    })
+}
+
+/// State shared between a `FadeHandle` and its thread.
+#[derive(Debug)]
+struct Shared {
+    control: Mutex<Control>,
+    /// Signalled whenever `control` changes, to interrupt waits.
+    changed: Condvar,
+}
+
+/// What a fade's thread has been asked to do, and where it's got to.
+#[derive(Debug)]
+struct Control {
+    /// Raw value the fade is heading for.
+    target: u32,
+    /// Raw value most recently written.
+    current: u32,
+    /// Set when `target` changes, so the thread starts a new fade from
+    /// `current`.
+    restart: bool,
+    /// Set by `FadeHandle::cancel`, to stop the thread where it is.
+    cancelled: bool,
+    /// Whether the thread is still going.
+    running: bool,
+}
+
+impl FadeHandle {
+    /// Starts fading `backlight` from its current setting to `target` over
+    /// `duration`, through `session`, taking `DEFAULT_RATE` steps a second.
+    ///
+    /// Returns `Error::OutOfRange` if `target` is above `backlight.max`.
+    pub fn start(
+        session: SessionProxyBlocking<'static>,
+        backlight: Backlight,
+        target: impl Into<Brightness>,
+        duration: Duration,
+        easing: Easing,
+    ) -> Result<Self, Error> {
+        Self::start_with_progress(
+            session,
+            backlight,
+            target,
+            duration,
+            easing,
+            |_| (),
+        )
+    }
+
+    /// Like `start`, but calling `on_step` with each raw value once it's been
+    /// written, on the fade's thread. This goes on across retargets, for as
+    /// long as the handle lasts.
+    pub fn start_with_progress(
+        session: SessionProxyBlocking<'static>,
+        backlight: Backlight,
+        target: impl Into<Brightness>,
+        duration: Duration,
+        easing: Easing,
+        on_step: impl FnMut(u32) + Send + 'static,
+    ) -> Result<Self, Error> {
+        let target = target.into().to_raw(&backlight);
+        check_range(target, backlight.max)?;
+        let current = backlight.read_current()?;
+        let set = {
+            let backlight = backlight.clone();
+            with_progress(
+                move |value| set_brightness(&session, &backlight, value),
+                on_step,
+            )
+        };
+        Self::launch(set, backlight, current, target, duration, easing)
+    }
+
+    /// Starts fading from raw value `current` to `target`, writing each step
+    /// with `set`.
+    fn launch(
+        set: Setter,
+        backlight: Backlight,
+        current: u32,
+        target: u32,
+        duration: Duration,
+        easing: Easing,
+    ) -> Result<Self, Error> {
+        let shared = Arc::new(Shared {
+            control: Mutex::new(Control {
+                target,
+                current,
+                restart: false,
+                cancelled: false,
+                running: false,
+            }),
+            changed: Condvar::new(),
+        });
+        let mut handle = FadeHandle {
+            shared,
+            set,
+            backlight,
+            duration,
+            easing,
+            thread: None,
+        };
+        let shared = Arc::clone(&handle.shared);
+        handle.spawn(&mut shared.lock())?;
+        Ok(handle)
+    }
+
+    /// Sends the fade towards `target` instead, starting again from wherever
+    /// it has got to, and taking the full duration from now. If the fade has
+    /// already finished, or was cancelled, this starts a new one.
+    ///
+    /// Returns `Error::OutOfRange` if `target` is above `backlight.max`. If
+    /// the last fade ended because a write failed, this returns that error
+    /// instead of starting a new one; trying again does start one.
+    pub fn retarget(
+        &mut self,
+        target: impl Into<Brightness>,
+    ) -> Result<(), Error> {
+        let target = target.into().to_raw(&self.backlight);
+        check_range(target, self.backlight.max)?;
+        let shared = Arc::clone(&self.shared);
+        let mut control = shared.lock();
+        control.target = target;
+        control.cancelled = false;
+        if control.running {
+            control.restart = true;
+            shared.changed.notify_all();
+        } else {
+            self.spawn(&mut control)?;
+        }
+        Ok(())
+    }
+
+    /// Stops the fade wherever it has got to.
+    pub fn cancel(&self) {
+        self.shared.lock().cancelled = true;
+        self.shared.changed.notify_all();
+    }
+
+    /// Checks whether the fade has finished, or stopped.
+    pub fn is_finished(&self) -> bool {
+        !self.shared.lock().running
+    }
+
+    /// Waits for the fade to finish or stop, and returns the raw value it
+    /// ended on. If a write failed along the way, returns that error.
+    pub fn wait(mut self) -> Result<u32, Error> {
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            // The thread doesn't panic unless zbus does.
+            Some(Err(panic)) => std::panic::resume_unwind(panic),
+            None => Ok(self.shared.lock().current),
+        }
+    }
+
+    /// Starts a thread to run the fade described by `control`, which must
+    /// be locked from `self.shared`. If there was a thread already, and it
+    /// failed, this returns its error rather than starting another.
+    fn spawn(&mut self, control: &mut Control) -> Result<(), Error> {
+        // Any earlier thread has said it's finished, through `control`, so
+        // this doesn't wait long.
+        if let Some(thread) = self.thread.take() {
+            match thread.join() {
+                Ok(result) => {
+                    result?;
+                }
+                // The thread doesn't panic unless zbus does.
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+        }
+        control.running = true;
+        control.restart = false;
+        let shared = Arc::clone(&self.shared);
+        let set = Arc::clone(&self.set);
+        let (duration, easing) = (self.duration, self.easing);
+        self.thread = Some(thread::spawn(move || {
+            let (mut control, result) =
+                fade_thread(&shared, &*set, duration, easing);
+            // Saying we're done before letting go of the lock means a
+            // retarget either comes in before, and `fade_thread` sees it, or
+            // after, and starts a new thread, so none get lost.
+            control.running = false;
+            result.map(|()| control.current)
+        }));
+        Ok(())
+    }
+}
+
+impl Shared {
+    /// Locks the control state. Nothing panics while holding the lock, but if
+    /// something did, the state would still make sense.
+    fn lock(&self) -> MutexGuard<'_, Control> {
+        self.control.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Runs the fades asked for through `shared`, writing each step with `set`,
+/// until one finishes without being retargeted, or the fade is cancelled, or
+/// a write fails. Returns with the control state still locked, so that the
+/// caller can say the thread is done before anything else looks at it.
+fn fade_thread<'a>(
+    shared: &'a Shared,
+    set: &dyn Fn(u32) -> Result<(), Error>,
+    duration: Duration,
+    easing: Easing,
+) -> (MutexGuard<'a, Control>, Result<(), Error>) {
+    let mut control = shared.lock();
+    'fade: loop {
+        control.restart = false;
+        let plan = Plan::new(
+            control.current,
+            control.target,
+            duration,
+            easing,
+            DEFAULT_RATE,
+        );
+        for (value, wait) in plan.steps() {
+            if value == control.current {
+                continue;
+            }
+            // Wait for the step to come due, unless we're interrupted.
+            while let Some(wait) = wait() {
+                if control.cancelled {
+                    return (control, Ok(()));
+                }
+                if control.restart {
+                    continue 'fade;
+                }
+                control = shared
+                    .changed
+                    .wait_timeout(control, wait)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0;
+            }
+            if control.cancelled {
+                return (control, Ok(()));
+            }
+            if control.restart {
+                continue 'fade;
+            }
+            // Don't hold up the caller while we talk to logind.
+            drop(control);
+            let result = set(value);
+            control = shared.lock();
+            if result.is_err() {
+                return (control, result);
+            }
+            control.current = value;
+        }
+        if !control.restart {
+            return (control, Ok(()));
+        }
+    }
+}
+
+/// The steps of a fade, worked out ahead of time.
+#[derive(Copy, Clone, Debug)]
+struct Plan {
+    from: u32,
+    to: u32,
+    duration: Duration,
+    easing: Easing,
+    steps: u32,
+    start: Instant,
+}
+
+impl Plan {
+    /// Plans a fade from raw value `from` to `to` over `duration`, starting
+    /// now, with `rate` steps a second.
+    fn new(
+        from: u32,
+        to: u32,
+        duration: Duration,
+        easing: Easing,
+        rate: u32,
+    ) -> Self {
+        let steps = (duration.as_secs_f64() * f64::from(rate)).round();
+        Plan {
+            from,
+            to,
+            duration,
+            easing,
+            steps: (steps as u32).max(1),
+            start: Instant::now(),
+        }
+    }
+
+    /// Returns each step's raw value, ending exactly at `to`, along with a
+    /// function giving how much longer there is to wait until it's due, if
+    /// any. Steps are scheduled from the start, rather than each waiting a
+    /// fixed time, so slow writes don't stretch the fade.
+    fn steps(
+        self,
+    ) -> impl Iterator<Item = (u32, impl Fn() -> Option<Duration>)> {
+        (1..=self.steps).map(move |i| {
+            let t = f64::from(i) / f64::from(self.steps);
+            let (from, to) = (f64::from(self.from), f64::from(self.to));
+            let value = from + (to - from) * self.easing.apply(t);
+            let due = self.duration.mul_f64(t);
+            let wait = move || due.checked_sub(self.start.elapsed());
+            (value.round() as u32, wait)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Collects the values of `plan`'s steps.
+    fn values(plan: Plan) -> Vec<u32> {
+        plan.steps().map(|(value, _)| value).collect()
+    }
+
+    #[test]
+    fn plan_ends_at_target() {
+        let second = Duration::from_secs(1);
+        let up = values(Plan::new(0, 1000, second, Easing::Linear, 10));
+        assert_eq!(up, [100, 200, 300, 400, 500, 600, 700, 800, 900, 1000]);
+        let down = values(Plan::new(1000, 7, second, Easing::EaseInOut, 60));
+        assert_eq!(down.len(), 60);
+        assert_eq!(down.last(), Some(&7));
+        assert!(down.windows(2).all(|w| w[0] >= w[1]));
+    }
+
+    #[test]
+    fn plan_takes_at_least_a_step() {
+        let plan = Plan::new(5, 50, Duration::ZERO, Easing::default(), 60);
+        assert_eq!(values(plan), [50]);
+        let plan = Plan::new(5, 50, Duration::from_secs(1), Easing::Linear, 0);
+        assert_eq!(values(plan), [50]);
+    }
+
+    #[test]
+    fn plan_schedules_from_start() {
+        let plan =
+            Plan::new(0, 100, Duration::from_secs(3600), Easing::Linear, 1);
+        let waits = plan
+            .steps()
+            .map(|(_, wait)| wait().unwrap())
+            .collect::<Vec<_>>();
+        // Each step is due a second after the last, counting from the start.
+        assert!(waits[0] <= Duration::from_secs(1));
+        assert!(waits[0] > Duration::from_millis(900));
+        assert!(waits[1] > Duration::from_millis(1900));
+        assert_eq!(waits.len(), 3600);
+    }
+
+    #[test]
+    fn retarget_as_fade_finishes() {
+        let bl = Backlight {
+            name: "test".to_string(),
+            subsystem: crate::Subsystem::Backlight,
+            max: 100,
+            step: 1,
+            source: crate::BrightnessFile::Brightness,
+        };
+        // Fades this short take a single step, and end right after it. The
+        // retarget goes in as soon as that step is written, with a little
+        // more delay each time, so that some land just as the fade finishes.
+        for delay in 0..500 {
+            let (written, wrote) = std::sync::mpsc::channel();
+            let set: Setter = Arc::new(move |value| {
+                let _ = written.send(value);
+                Ok(())
+            });
+            let mut fade = FadeHandle::launch(
+                set,
+                bl.clone(),
+                0,
+                10,
+                Duration::ZERO,
+                Easing::Linear,
+            )
+            .unwrap();
+            assert_eq!(wrote.recv().unwrap(), 10);
+            for _ in 0..delay {
+                std::hint::spin_loop();
+            }
+            fade.retarget(20).unwrap();
+            assert_eq!(fade.wait().unwrap(), 20);
+        }
+    }
+
+    #[test]
+    fn progress_follows_writes() {
+        let bl = Backlight {
+            name: "test".to_string(),
+            subsystem: crate::Subsystem::Backlight,
+            max: 100,
+            step: 1,
+            source: crate::BrightnessFile::Brightness,
+        };
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let (told, progress) = std::sync::mpsc::channel();
+        let set = {
+            let written = Arc::clone(&written);
+            move |value| {
+                written.lock().unwrap().push(value);
+                // A failed write shouldn't be reported as progress.
+                if value > 50 {
+                    return Err(Error::AtLimit(value));
+                }
+                Ok(())
+            }
+        };
+        let set = with_progress(set, move |value| {
+            let _ = told.send(value);
+        });
+        let fade = FadeHandle::launch(
+            set,
+            bl,
+            0,
+            100,
+            Duration::from_millis(100),
+            Easing::Linear,
+        )
+        .unwrap();
+        assert!(fade.wait().is_err());
+        let progress = progress.try_iter().collect::<Vec<_>>();
+        let written = written.lock().unwrap();
+        assert!(written.last() > Some(&50));
+        assert_eq!(progress, written[..written.len() - 1]);
+    }
+
+    #[test]
+    fn easing_stays_in_bounds() {
+        for easing in [
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+        ] {
+            assert_eq!(easing.apply(0.), 0.);
+            assert_eq!(easing.apply(1.), 1.);
+            assert_eq!(easing.apply(-1.), 0.);
+            assert_eq!(easing.apply(2.), 1.);
+        }
+        assert!(Easing::EaseIn.apply(0.5) < 0.5);
+        assert!(Easing::EaseOut.apply(0.5) > 0.5);
+        assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+    }
+}
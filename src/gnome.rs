@@ -0,0 +1,50 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Adjusting the backlight through GNOME's settings daemon.
+//!
+//! Under GNOME, going through `gnome-settings-daemon` instead of logind means
+//! GNOME's own brightness slider and on-screen display stay in sync with our
+//! changes. The daemon works in percent rather than raw units, and only
+//! controls the built-in panel, so there's no way to pick a device here.
+
+use crate::Error;
+use zbus::blocking::Connection;
+
+/// The part of the settings daemon's interface we use.
+#[zbus::proxy(
+    interface = "org.gnome.SettingsDaemon.Power.Screen",
+    default_service = "org.gnome.SettingsDaemon.Power",
+    default_path = "/org/gnome/SettingsDaemon/Power"
+)]
+trait Screen {
+    /// Current brightness in percent, or -1 if there's no panel to control.
+    #[zbus(property)]
+    fn brightness(&self) -> zbus::Result<i32>;
+    /// Changes the brightness, in percent.
+    #[zbus(property)]
+    fn set_brightness(&self, value: i32) -> zbus::Result<()>;
+}
+
+/// Connects to the settings daemon. Unlike logind, it lives on the SESSION
+/// bus.
+fn connect() -> Result<ScreenProxyBlocking<'static>, Error> {
+    let conn = Connection::session()?;
+    Ok(ScreenProxyBlocking::new(&conn)?)
+}
+
+/// Asks the settings daemon for the current brightness, as a percentage.
+pub fn get_brightness() -> Result<u32, Error> {
+    let pct = connect()?.brightness()?;
+    // The daemon reports -1 if there's no panel it can control.
+    u32::try_from(pct).map_err(|_| Error::EternalDarkness)
+}
+
+/// Asks the settings daemon to set the brightness to `percent`.
+///
+/// Returns `Error::OutOfRange` if `percent` is more than 100.
+pub fn set_brightness(percent: u32) -> Result<(), Error> {
+    crate::check_range(percent, 100)?;
+    Ok(connect()?.set_brightness(percent as i32)?)
+}
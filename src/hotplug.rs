@@ -0,0 +1,222 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Finding out when backlight devices appear and disappear, for daemons that
+//! need to look for devices again when a dock is plugged in or a GPU driver
+//! is reloaded.
+//!
+//! This is only available on Linux, with the `hotplug` feature. It listens
+//! for the kernel's own device announcements ("uevents") over netlink, the
+//! same ones udev hears, so it doesn't need udev to be running.
+
+use crate::{Error, Subsystem};
+use rustix::{
+    event::{poll, PollFd, PollFlags},
+    io::Errno,
+    net::{
+        bind,
+        netlink::{self, SocketAddrNetlink},
+        recvfrom, socket_with, AddressFamily, RecvFlags, SocketFlags,
+        SocketType,
+    },
+};
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
+
+/// The netlink multicast group the kernel announces devices on. (Group 2 is
+/// udev passing them on, after it has processed them.)
+const KERNEL_GROUP: u32 = 1;
+
+/// A device appearing or disappearing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DeviceEvent {
+    /// A device was added. Its files in sysfs are ready to read by the time
+    /// this is reported.
+    Added {
+        /// Which kind of device it is.
+        subsystem: Subsystem,
+        /// The device's name, as in `Backlight::name`.
+        name: String,
+    },
+    /// A device was removed.
+    Removed {
+        /// Which kind of device it was.
+        subsystem: Subsystem,
+        /// The device's name, as in `Backlight::name`.
+        name: String,
+    },
+}
+
+/// Listens for backlights, and LEDs like keyboard backlights, being added and
+/// removed. As an `Iterator`, this blocks until something happens, and never
+/// ends.
+///
+/// Programs with their own event loop can instead wait for the file
+/// descriptor from `as_fd` to become readable, and then call `check`, which
+/// never blocks.
+///
+/// Only changes after the monitor is created are reported, so create it
+/// before looking for devices, so that none are missed in between.
+#[derive(Debug)]
+pub struct DeviceMonitor {
+    socket: OwnedFd,
+}
+
+impl DeviceMonitor {
+    /// Starts listening.
+    pub fn new() -> Result<Self, Error> {
+        let socket = socket_with(
+            AddressFamily::NETLINK,
+            SocketType::DGRAM,
+            SocketFlags::CLOEXEC | SocketFlags::NONBLOCK,
+            Some(netlink::KOBJECT_UEVENT),
+        )
+        .map_err(sys_error)?;
+        bind(&socket, &SocketAddrNetlink::new(0, KERNEL_GROUP))
+            .map_err(sys_error)?;
+        Ok(Self { socket })
+    }
+
+    /// Takes the next device added or removed, without waiting, or returns
+    /// `None` if there's nothing new.
+    pub fn check(&mut self) -> Result<Option<DeviceEvent>, Error> {
+        // The kernel keeps announcements well under this size.
+        let mut buffer = [0; 8192];
+        loop {
+            let (len, _, from) = match recvfrom(
+                &self.socket,
+                &mut buffer[..],
+                RecvFlags::empty(),
+            ) {
+                Ok(received) => received,
+                Err(Errno::AGAIN) => return Ok(None),
+                Err(Errno::INTR) => continue,
+                Err(e) => return Err(sys_error(e)),
+            };
+            // Only the kernel can tell us about devices; anything else on the
+            // group is some other program's business, or a forgery.
+            let from_kernel = from
+                .and_then(|from| SocketAddrNetlink::try_from(from).ok())
+                .is_some_and(|from| from.pid() == 0);
+            if !from_kernel {
+                continue;
+            }
+            if let Some(event) = parse(&buffer[..len]) {
+                return Ok(Some(event));
+            }
+        }
+    }
+}
+
+impl Iterator for DeviceMonitor {
+    type Item = Result<DeviceEvent, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.check() {
+                Ok(Some(event)) => return Some(Ok(event)),
+                Ok(None) => (),
+                Err(e) => return Some(Err(e)),
+            }
+            let mut fds = [PollFd::new(&self.socket, PollFlags::IN)];
+            match poll(&mut fds, None) {
+                Ok(_) | Err(Errno::INTR) => (),
+                Err(e) => return Some(Err(sys_error(e))),
+            }
+        }
+    }
+}
+
+impl AsFd for DeviceMonitor {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.socket.as_fd()
+    }
+}
+
+/// Reads a kernel announcement, which is a header like
+/// `add@/devices/.../backlight/intel_backlight` followed by `KEY=value`
+/// fields, all separated by NULs. Returns `None` unless it's a device we care
+/// about being added or removed.
+fn parse(message: &[u8]) -> Option<DeviceEvent> {
+    let message = std::str::from_utf8(message).ok()?;
+    let (mut action, mut subsystem, mut path) = (None, None, None);
+    for field in message.split('\0').skip(1) {
+        match field.split_once('=') {
+            Some(("ACTION", value)) => action = Some(value),
+            Some(("SUBSYSTEM", value)) => subsystem = Some(value),
+            Some(("DEVPATH", value)) => path = Some(value),
+            _ => (),
+        }
+    }
+    let subsystem = match subsystem? {
+        "backlight" => Subsystem::Backlight,
+        "leds" => Subsystem::Leds,
+        _ => return None,
+    };
+    let name = path?.rsplit('/').next()?.to_string();
+    match action? {
+        "add" => Some(DeviceEvent::Added { subsystem, name }),
+        "remove" => Some(DeviceEvent::Removed { subsystem, name }),
+        _ => None,
+    }
+}
+
+/// Wraps error `e`, from the netlink socket.
+fn sys_error(e: Errno) -> Error {
+    Error::SysAccess(e.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a kernel announcement of `action` on the device at `path`.
+    fn uevent(action: &str, path: &str, subsystem: &str) -> Vec<u8> {
+        format!(
+            "{action}@{path}\0ACTION={action}\0DEVPATH={path}\0\
+             SUBSYSTEM={subsystem}\0SEQNUM=4242\0"
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn parses_adds_and_removes() {
+        let path = "/devices/pci0000:00/0000:00:02.0/drm/card1/\
+                    card1-eDP-1/intel_backlight";
+        assert_eq!(
+            parse(&uevent("add", path, "backlight")),
+            Some(DeviceEvent::Added {
+                subsystem: Subsystem::Backlight,
+                name: "intel_backlight".to_string(),
+            }),
+        );
+        let path = "/devices/platform/thinkpad_acpi/leds/tpacpi::kbd_backlight";
+        assert_eq!(
+            parse(&uevent("remove", path, "leds")),
+            Some(DeviceEvent::Removed {
+                subsystem: Subsystem::Leds,
+                name: "tpacpi::kbd_backlight".to_string(),
+            }),
+        );
+    }
+
+    #[test]
+    fn ignores_the_rest() {
+        let path = "/devices/virtual/backlight/acpi_video0";
+        // Changes to a device that's already there.
+        assert_eq!(parse(&uevent("change", path, "backlight")), None);
+        // Other kinds of device.
+        assert_eq!(
+            parse(&uevent("add", "/devices/virtual/net/lo", "net")),
+            None
+        );
+        // Missing fields, and messages that aren't text.
+        assert_eq!(
+            parse(b"add@/devices/virtual/backlight/x\0ACTION=add\0"),
+            None
+        );
+        assert_eq!(parse(b"add@\xff\0ACTION=add\0SUBSYSTEM=backlight\0"), None);
+        assert_eq!(parse(b""), None);
+    }
+}
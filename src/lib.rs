@@ -7,9 +7,34 @@
 //! This crate uses systemd and logind to set the backlight without requiring
 //! root privileges. It will only work when run by a user who is currently
 //! logged in at the seat that controls the display in question.
+//!
+//! On OpenBSD, the `wscons` module can adjust the display instead. The rest of
+//! the crate still builds there, but it's all Linux-specific: logind and
+//! `/sys/class` don't exist, so discovery finds no devices, and connecting to
+//! logind fails. The `watch` and `hotplug` modules are only built on Linux.
+
+pub mod adjust;
+pub mod controller;
+#[cfg(feature = "async")]
+pub mod events;
+pub mod fade;
+pub mod gnome;
+#[cfg(all(feature = "hotplug", target_os = "linux"))]
+pub mod hotplug;
+pub mod logind;
+pub mod upower;
+#[cfg(all(feature = "watch", target_os = "linux"))]
+pub mod watch;
+#[cfg(target_os = "openbsd")]
+pub mod wscons;
 
-use logind_zbus::session::{SessionProxyBlocking, SessionProxy};
-use std::{fs, io, path::Path};
+pub use logind::{SessionProxy, SessionProxyBlocking};
+use std::{
+    fs,
+    io::{self, Write},
+    num::IntErrorKind,
+    path::{Path, PathBuf},
+};
 use zbus::blocking::Connection;
 
 /// A description of a backlight device found by this library.
@@ -18,13 +43,391 @@ pub struct Backlight {
     /// Name of the backlight. Despite being a "device name" this is not a name
     /// you'll find in `/dev`. It appears in two places:
     ///
-    /// - As a directory under `/sys/class/backlight/`
+    /// - As a directory under `/sys/class/backlight/` (or the directory for
+    ///   its `subsystem`)
     /// - As the name passed to `logind` to control the backlight.
     pub name: String,
 
+    /// The kernel subsystem the device belongs to. This is usually
+    /// `Subsystem::Backlight`, but keyboard backlights are LEDs.
+    pub subsystem: Subsystem,
+
     /// Highest raw value the backlight supports. This value always means "fully
     /// on," but different drivers use different units and scales.
     pub max: u32,
+
+    /// Smallest change in raw value that makes a visible difference. Some
+    /// hardware has far fewer real levels than its raw range suggests, and
+    /// rounds every setting to the nearest one, so changes smaller than this
+    /// are wasted. The library can't tell this by looking, so it's 1 unless
+    /// the caller knows better, from measuring the device or a known quirk.
+    pub step: u32,
+
+    /// Which file `read_current` reads the current setting from. Discovery
+    /// always reads `brightness`; `prefer_actual` switches to
+    /// `actual_brightness` where the device has it.
+    pub source: BrightnessFile,
+}
+
+/// The sysfs files a device's current setting can be read from.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum BrightnessFile {
+    /// `brightness`, the last value written. This is what logind changes, so
+    /// it follows every change straight away.
+    #[default]
+    Brightness,
+    /// `actual_brightness`, what the hardware is really showing. Some drivers,
+    /// like `amdgpu`, or `intel_backlight` in the middle of a fade, report
+    /// something different here.
+    ActualBrightness,
+}
+
+impl BrightnessFile {
+    /// Returns the name of the file, within the device's directory.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            BrightnessFile::Brightness => "brightness",
+            BrightnessFile::ActualBrightness => "actual_brightness",
+        }
+    }
+}
+
+/// One of the colors of a multicolor LED, like an RGB keyboard backlight.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColorChannel {
+    /// Name of the color, as the kernel gives it in `multi_index`, like
+    /// `red`.
+    pub color: String,
+    /// How bright this color is at full brightness, from 0 to the device's
+    /// `max`, as in `multi_intensity`.
+    pub intensity: u32,
+}
+
+/// The kernel subsystems, or device classes, whose brightness logind can
+/// change.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Subsystem {
+    /// Display backlights, in `/sys/class/backlight`.
+    #[default]
+    Backlight,
+    /// LEDs, in `/sys/class/leds`. This includes most keyboard backlights,
+    /// which are named like `NAME::kbd_backlight`.
+    Leds,
+}
+
+impl Subsystem {
+    /// Returns the subsystem's name, as sysfs and logind know it.
+    pub fn name(self) -> &'static str {
+        match self {
+            Subsystem::Backlight => "backlight",
+            Subsystem::Leds => "leds",
+        }
+    }
+
+    /// Returns the directory where the kernel lists devices in this
+    /// subsystem. Like `backlight_dir`, this honors `BRIGHTR_SYSFS`.
+    pub fn dir(self) -> PathBuf {
+        sysfs_root().join("class").join(self.name())
+    }
+}
+
+impl Backlight {
+    /// Rounds raw value `value` to the nearest level the hardware can really
+    /// show, according to `step`. The top of the range always counts as a
+    /// level, even if it isn't a multiple of `step`.
+    pub fn quantize(&self, value: u32) -> u32 {
+        let step = self.step.max(1);
+        let value = value.min(self.max);
+        let below = value - value % step;
+        let above = below.saturating_add(step).min(self.max);
+        if value - below < above - value {
+            below
+        } else {
+            above
+        }
+    }
+
+    /// Converts `fraction` of the way up the range, from 0 to 1, into the
+    /// nearest raw value. Most hardware is roughly linear in raw values, while
+    /// eyes are far more sensitive to changes when it's dark, so `exponent` is
+    /// applied to the fraction first: 1 is linear, and higher values spend
+    /// more of the range on the dim end. Fractions outside 0 to 1 are clamped.
+    pub fn raw_from_fraction(&self, fraction: f64, exponent: f64) -> u32 {
+        let fraction = fraction.clamp(0., 1.).powf(exponent);
+        (fraction * f64::from(self.max)).round() as u32
+    }
+
+    /// Converts raw value `raw` into a fraction of the way up the range, from
+    /// 0 to 1, undoing `exponent` as applied by `raw_from_fraction`. Values
+    /// above `max` count as `max`.
+    pub fn fraction_from_raw(&self, raw: u32, exponent: f64) -> f64 {
+        if self.max == 0 {
+            return 0.;
+        }
+        (f64::from(raw.min(self.max)) / f64::from(self.max)).powf(1. / exponent)
+    }
+
+    /// Converts percentage `pct` into the nearest raw value, using `exponent`
+    /// as `raw_from_fraction` does. Percentages over 100 count as 100.
+    pub fn raw_from_percent(&self, pct: u32, exponent: f64) -> u32 {
+        self.raw_from_fraction(f64::from(pct) / 100., exponent)
+    }
+
+    /// Converts raw value `raw` into the nearest whole percentage, undoing
+    /// `exponent` as `fraction_from_raw` does, so that it round-trips with
+    /// `raw_from_percent` wherever the hardware has enough levels to tell
+    /// percentages apart.
+    pub fn percent_from_raw(&self, raw: u32, exponent: f64) -> u32 {
+        (self.fraction_from_raw(raw, exponent) * 100.).round() as u32
+    }
+
+    /// Reads the device's current raw setting again, without going through
+    /// discovery, so it's cheap enough to poll. This comes from the file
+    /// given by `source`. Like discovery, this reports values above `max` as
+    /// `max`.
+    pub fn read_current(&self) -> Result<u32, Error> {
+        Ok(self.read_attribute(self.source.file_name())?.min(self.max))
+    }
+
+    /// Makes `read_current` read `actual_brightness` from now on, if the
+    /// device has it, and returns the setting read from there. Devices
+    /// without it, like LEDs, carry on with `brightness`; check `source` to
+    /// see which was used.
+    pub fn prefer_actual(&mut self) -> Result<u32, Error> {
+        match self.try_read_actual()? {
+            Some(actual) => {
+                self.source = BrightnessFile::ActualBrightness;
+                Ok(actual)
+            }
+            None => {
+                self.source = BrightnessFile::Brightness;
+                self.read_current()
+            }
+        }
+    }
+
+    /// Reads what the hardware is actually showing, from the device's
+    /// `actual_brightness`. This usually matches `read_current`, but not for
+    /// drivers that ignore some writes, or while something else, like the
+    /// firmware, is changing the brightness. Devices without
+    /// `actual_brightness`, like LEDs, report `brightness` instead; use
+    /// `try_read_actual` to tell. Like `read_current`, this reports values
+    /// above `max` as `max`.
+    pub fn read_actual(&self) -> Result<u32, Error> {
+        match self.try_read_actual()? {
+            Some(actual) => Ok(actual),
+            None => self.read_current(),
+        }
+    }
+
+    /// Like `read_actual`, but returns `None` for devices without
+    /// `actual_brightness`, rather than reading `brightness`. Checking whether
+    /// the hardware took a change needs this, since `brightness` only says
+    /// what was written. This too reports values above `max` as `max`.
+    pub fn try_read_actual(&self) -> Result<Option<u32>, Error> {
+        match self.read_attribute("actual_brightness") {
+            Ok(actual) => Ok(Some(actual.min(self.max))),
+            Err(Error::Access(_, e)) if e.kind() == io::ErrorKind::NotFound => {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Checks whether the backlight is switched on, according to the device's
+    /// `bl_power`. A backlight that's switched off stays dark whatever its
+    /// brightness, and keeps its setting for when it's switched back on.
+    ///
+    /// Returns `Error::NoPowerControl` if the device has no `bl_power`.
+    pub fn read_power(&self) -> Result<bool, Error> {
+        match self.read_attribute("bl_power") {
+            // Anything but 0, "unblank," means some degree of off.
+            Ok(bl_power) => Ok(bl_power == 0),
+            Err(Error::Access(_, e)) if e.kind() == io::ErrorKind::NotFound => {
+                Err(Error::NoPowerControl(self.name.clone()))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Finds out which kernel trigger, if any, is driving an LED, like
+    /// `kbd-backlight` or `timer`. A trigger changes the brightness by itself,
+    /// and may undo changes made any other way, until `none` is written to
+    /// the LED's `trigger`. Returns `None` for LEDs with no trigger active, and
+    /// for devices, like display backlights, that can't have one.
+    pub fn trigger(&self) -> Result<Option<String>, Error> {
+        let path = self.path().join("trigger");
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(Error::Access(path.display().to_string(), e));
+            }
+        };
+        Ok(active_trigger(&text).map(str::to_string))
+    }
+
+    /// Reads the colors of a multicolor LED, from its `multi_index` and
+    /// `multi_intensity`. These set the LED's hue: the kernel lights each
+    /// color at its intensity scaled by `brightness` over `max`, so changing
+    /// the brightness in the usual ways dims the LED without changing its
+    /// color. Returns `None` for devices that aren't multicolor.
+    pub fn read_colors(&self) -> Result<Option<Vec<ColorChannel>>, Error> {
+        let index_path = self.path().join("multi_index");
+        let index = match fs::read_to_string(&index_path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(Error::Access(index_path.display().to_string(), e));
+            }
+        };
+        let path = self.path().join("multi_intensity");
+        let intensities = fs::read_to_string(&path)
+            .map_err(|e| Error::Access(path.display().to_string(), e))?;
+        parse_colors(&path, &index, &intensities).map(Some)
+    }
+
+    /// Reads and parses sysfs attribute `name` of the device.
+    fn read_attribute(&self, name: &str) -> Result<u32, Error> {
+        let path = self.path().join(name);
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| Error::Access(path.display().to_string(), e))?;
+        parse_attribute(&path, &contents)
+    }
+
+    /// Returns the device's directory in sysfs.
+    pub fn path(&self) -> PathBuf {
+        self.subsystem.dir().join(&self.name)
+    }
+
+    /// Reads the kind of interface the device is, as the kernel reports it:
+    /// `raw` for one driven directly by the graphics driver, `platform` for
+    /// one driven by a laptop's platform driver, or `firmware` for one that
+    /// goes through ACPI or similar.
+    pub fn kind(&self) -> Result<String, Error> {
+        let path = self.path().join("type");
+        let text = fs::read_to_string(&path)
+            .map_err(|e| Error::Access(path.display().to_string(), e))?;
+        Ok(text.trim().to_string())
+    }
+
+    /// Works out the name of the kernel driver behind the device, like
+    /// `i915` or `amdgpu`, if there is one. This comes from the parent
+    /// device, since the backlight itself has no driver.
+    pub fn driver(&self) -> Option<String> {
+        let parent = self.path().join("device");
+        if let Ok(uevent) = fs::read_to_string(parent.join("uevent")) {
+            let driver = uevent.lines().find_map(|l| l.strip_prefix("DRIVER="));
+            if let Some(driver) = driver {
+                return Some(driver.to_string());
+            }
+        }
+        // Not every bus puts the driver in uevent, but the link is always
+        // there when one is bound.
+        let link = fs::read_link(parent.join("driver")).ok()?;
+        Some(link.file_name()?.to_str()?.to_string())
+    }
+
+    /// Finds the sysfs directory of the device the backlight belongs to,
+    /// such as a graphics card or one of its connectors, if it has one.
+    pub fn parent(&self) -> Option<PathBuf> {
+        fs::canonicalize(self.path().join("device")).ok()
+    }
+
+    /// Works out which display connector the backlight lights, like
+    /// `card1-eDP-1`, where the part after the card is the output name that
+    /// display servers use. Drivers like `i915` make the connector the
+    /// backlight's parent; others, like `amdgpu`, hang the backlight off the
+    /// graphics card, in which case this finds the card's built-in panel, if
+    /// it has exactly one. Firmware backlights, like `acpi_video0`, have no
+    /// connector.
+    pub fn connector(&self) -> Option<String> {
+        let parent = self.parent()?;
+        let name = parent.file_name()?.to_str()?;
+        if output_name(name).is_some() {
+            return Some(name.to_string());
+        }
+        let cards = fs::read_dir(parent.join("drm")).ok()?;
+        let mut panels = cards
+            .flatten()
+            .filter_map(|card| fs::read_dir(card.path()).ok())
+            .flatten()
+            .flatten()
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| {
+                output_name(name).is_some_and(|output| {
+                    ["eDP-", "LVDS-", "DSI-"]
+                        .iter()
+                        .any(|kind| output.starts_with(kind))
+                })
+            });
+        match (panels.next(), panels.next()) {
+            (Some(panel), None) => Some(panel),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the output part of DRM connector name `name`, like `eDP-1` for
+/// `card1-eDP-1`, or `None` if `name` isn't a connector.
+fn output_name(name: &str) -> Option<&str> {
+    let (card, output) = name.strip_prefix("card")?.split_once('-')?;
+    let is_card = !card.is_empty() && card.bytes().all(|b| b.is_ascii_digit());
+    (is_card && !output.is_empty()).then_some(output)
+}
+
+/// A brightness in one of the units people tend to think in, so that raw
+/// values and percentages can't be mixed up by accident. Functions that set
+/// the brightness accept any of these, or a plain `u32`, which is taken as a
+/// raw value.
+///
+/// Percentages and fractions are linear in raw values; to apply an exponent,
+/// convert with `Backlight::raw_from_percent` and pass the raw result.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Brightness {
+    /// The driver's own raw value, from 0 to the backlight's `max`.
+    Raw(u32),
+    /// Percentage of the backlight's `max`, from 0 to 100.
+    Percent(f64),
+    /// Fraction of the backlight's `max`, from 0 to 1.
+    Fraction(f64),
+}
+
+impl Brightness {
+    /// Converts this into a raw value for `backlight`, rounding to the
+    /// nearest. Percentages and fractions outside their range are clamped, but
+    /// raw values are returned as they are, so may be above `backlight.max`.
+    pub fn to_raw(self, backlight: &Backlight) -> u32 {
+        match self {
+            Brightness::Raw(raw) => raw,
+            Brightness::Percent(pct) => {
+                backlight.raw_from_fraction(pct / 100., 1.)
+            }
+            Brightness::Fraction(fraction) => {
+                backlight.raw_from_fraction(fraction, 1.)
+            }
+        }
+    }
+
+    /// Converts this into a fraction of `backlight`'s range, from 0 to 1.
+    pub fn to_fraction(self, backlight: &Backlight) -> f64 {
+        match self {
+            Brightness::Raw(raw) => backlight.fraction_from_raw(raw, 1.),
+            Brightness::Percent(pct) => (pct / 100.).clamp(0., 1.),
+            Brightness::Fraction(fraction) => fraction.clamp(0., 1.),
+        }
+    }
+
+    /// Converts this into a percentage of `backlight`'s range, from 0 to 100.
+    pub fn to_percent(self, backlight: &Backlight) -> f64 {
+        self.to_fraction(backlight) * 100.
+    }
+}
+
+impl From<u32> for Brightness {
+    fn from(raw: u32) -> Self {
+        Brightness::Raw(raw)
+    }
 }
 
 /// Things that can go wrong when using this library.
@@ -42,16 +445,122 @@ pub enum Error {
     /// A backlight device produced non-numeric output, which is super weird.
     #[error("backlight device {0} produced non-numeric output: {1}")]
     Parsing(String, String, #[source] std::num::ParseIntError),
+    /// A backlight device produced no output at all. Some drivers do this
+    /// briefly while they're starting up, so it may be worth trying again.
+    #[error("backlight device {0} is empty; is its driver still starting?")]
+    Empty(String),
+    /// A brightness was asked for that's above the device's maximum.
+    #[error("brightness {value} is out of range (maximum is {max})")]
+    OutOfRange {
+        /// The value asked for.
+        value: u32,
+        /// The highest value the device accepts.
+        max: u32,
+    },
+    /// A step was asked for that can't go anywhere, because the brightness is
+    /// already at the limit it moves towards.
+    #[error("brightness is already at its limit of {0}")]
+    AtLimit(u32),
+    /// The device can't be switched on and off, because it has no `bl_power`.
+    /// LEDs never do, and some display drivers don't either.
+    #[error("backlight device {0} has no bl_power, so can't be switched off")]
+    NoPowerControl(String),
 
     /// Something happened in communication with logind.
     #[error("problem changing brightness over DBus")]
     Dbus(#[from] zbus::Error),
 }
 
+impl Error {
+    /// Returns a number identifying the kind of error, for callers that can't
+    /// match on the enum, like other languages. These numbers never change
+    /// meaning, and new kinds of error get new numbers.
+    pub fn code(&self) -> u32 {
+        match self {
+            Error::EternalDarkness => 1,
+            Error::SysAccess(_) => 2,
+            Error::Access(..) => 3,
+            Error::Parsing(..) => 4,
+            Error::Empty(_) => 5,
+            Error::Dbus(_) => 6,
+            Error::OutOfRange { .. } => 7,
+            Error::AtLimit(_) => 8,
+            Error::NoPowerControl(_) => 9,
+        }
+    }
+
+    /// Checks whether this error means we weren't allowed to do something,
+    /// either by the kernel or by logind.
+    pub fn is_permission_denied(&self) -> bool {
+        match self {
+            Error::SysAccess(e) | Error::Access(_, e) => {
+                e.kind() == io::ErrorKind::PermissionDenied
+            }
+            Error::Dbus(zbus::Error::MethodError(name, _, _)) => matches!(
+                name.as_str().strip_prefix("org.freedesktop.DBus.Error."),
+                Some("AccessDenied" | "InteractiveAuthorizationRequired")
+            ),
+            Error::Dbus(zbus::Error::FDO(e)) => matches!(
+                **e,
+                zbus::fdo::Error::AccessDenied(_)
+                    | zbus::fdo::Error::InteractiveAuthorizationRequired(_)
+            ),
+            _ => false,
+        }
+    }
+
+    /// Checks whether this error means there's no backlight device to use:
+    /// either none at all, or not the one asked for.
+    pub fn is_no_device(&self) -> bool {
+        match self {
+            Error::EternalDarkness => true,
+            Error::SysAccess(e) | Error::Access(_, e) => {
+                e.kind() == io::ErrorKind::NotFound
+            }
+            _ => false,
+        }
+    }
+
+    /// Checks whether this error means a value was out of range for the
+    /// device, which is the caller's mistake rather than the system's.
+    pub fn is_out_of_range(&self) -> bool {
+        matches!(self, Error::OutOfRange { .. })
+    }
+}
+
+/// Returns `Error::OutOfRange` if `value` is above `max`.
+fn check_range(value: u32, max: u32) -> Result<(), Error> {
+    if value > max {
+        return Err(Error::OutOfRange { value, max });
+    }
+    Ok(())
+}
+
+/// Returns the directory where the kernel lists backlight devices. This is
+/// normally `/sys/class/backlight`, but if the `BRIGHTR_SYSFS` environment
+/// variable is set, it's taken as the place sysfs is mounted instead. That's
+/// mostly useful for testing against a fake device tree.
+pub fn backlight_dir() -> PathBuf {
+    Subsystem::Backlight.dir()
+}
+
+/// Returns the place sysfs is mounted: `/sys`, unless the `BRIGHTR_SYSFS`
+/// environment variable says otherwise, as for `backlight_dir`. This is for
+/// finding other parts of sysfs, like thermal zones, in the same tree.
+pub fn sysfs_root() -> PathBuf {
+    std::env::var_os("BRIGHTR_SYSFS")
+        .map_or_else(|| PathBuf::from("/sys"), PathBuf::from)
+}
+
 /// Locates the first suitable backlight device in `/sys/class/backlight`. Since
 /// most systems have either zero or one backlight, this limited operation
 /// covers a lot of use cases.
 ///
+/// Where there's more than one, we go by their `kind`: `raw` devices first,
+/// since they drive the panel directly, then `platform`, and `firmware` last,
+/// since firmware interfaces are often left over and do nothing. Devices of
+/// the same kind are taken in order of name.
+///
 /// On success, returns both the `Backlight` and its current raw setting.
 pub fn find_first_backlight() -> Result<(Backlight, u32), Error> {
     // The Session proxy in logind will happily let us set the backlight, if we
@@ -62,43 +571,105 @@ pub fn find_first_backlight() -> Result<(Backlight, u32), Error> {
     // Fortunately the hard way is available to unprivileged users, and that's
     // presumably why logind didn't offer to proxy it for us.
 
-    let dir = fs::read_dir("/sys/class/backlight").map_err(Error::SysAccess)?;
+    let mut found = enumerate_backlights()?;
+    // The sort is stable, so this keeps each kind in order of name. Caching
+    // the key reads each device's `type` once, rather than on every
+    // comparison.
+    found.sort_by_cached_key(|(backlight, _)| {
+        match backlight.kind().as_deref() {
+            Ok("raw") => 0,
+            Ok("platform") => 1,
+            Ok("firmware") => 2,
+            _ => 3,
+        }
+    });
+    found.into_iter().next().ok_or(Error::EternalDarkness)
+}
 
+/// Locates every suitable backlight device in `/sys/class/backlight`, sorted
+/// by name, for machines with more than one. Devices that can't be read are
+/// skipped, as they are by `find_first_backlight`.
+///
+/// Returns each `Backlight` along with its current raw setting. If there are
+/// no devices, the result is empty, rather than an error.
+pub fn enumerate_backlights() -> Result<Vec<(Backlight, u32)>, Error> {
+    let dir = fs::read_dir(backlight_dir()).map_err(Error::SysAccess)?;
+
+    let mut found = vec![];
     for dirent in dir {
         let dirent = dirent.map_err(Error::SysAccess)?;
-        let path = dirent.path();
-
-        match read_backlight_settings(&path) {
-            Ok((current, max)) => {
-                // We'll take the first one we found.
-
-                // This error case really shouldn't be possible since we built
-                // the path by appending a name!
-                let name = path.file_name().expect("file should have a name");
-                // This error _is_ possible but unusual.
-                let Some(name) = name.to_str() else {
-                    eprintln!("skipping non-UTF8 backlight device: {name:?}");
-                    continue;
-                };
-
-                return Ok((
-                    Backlight {
-                        name: name.to_owned(),
-                        max,
-                    },
-                    current,
-                ));
-            }
-            Err(e) => {
-                eprintln!(
-                    "skipping backlight-like device at {}: {e}",
-                    path.display()
-                );
+        found.extend(read_backlight(&dirent.path(), Subsystem::Backlight));
+    }
+    found.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+    Ok(found)
+}
+
+/// Locates every keyboard backlight in `/sys/class/leds`, sorted by name.
+/// These are LEDs whose names contain `::kbd_backlight`, which logind can
+/// change just like displays, with no need for UPower.
+///
+/// Returns each `Backlight` along with its current raw setting. If there are
+/// none, the result is empty, rather than an error.
+pub fn find_keyboard_backlights() -> Result<Vec<(Backlight, u32)>, Error> {
+    let dir = match fs::read_dir(Subsystem::Leds.dir()) {
+        Ok(dir) => dir,
+        // Machines with no LEDs at all don't have the class.
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(Error::SysAccess(e)),
+    };
+
+    let mut found = vec![];
+    for dirent in dir {
+        let dirent = dirent.map_err(Error::SysAccess)?;
+        let name = dirent.file_name();
+        if name.to_string_lossy().contains("::kbd_backlight") {
+            let led = read_backlight(&dirent.path(), Subsystem::Leds);
+            if let Some((bl, _)) = &led {
+                warn_about_trigger(bl);
             }
+            found.extend(led);
         }
     }
+    found.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+    Ok(found)
+}
+
+/// Reads the device at `path`, in `subsystem`, returning it and its current
+/// raw setting, or explaining on stderr why it's being skipped.
+fn read_backlight(
+    path: &Path,
+    subsystem: Subsystem,
+) -> Option<(Backlight, u32)> {
+    match read_backlight_settings(path) {
+        Ok((current, max)) => {
+            // This error case really shouldn't be possible since we built
+            // the path by appending a name!
+            let name = path.file_name().expect("file should have a name");
+            // This error _is_ possible but unusual.
+            let Some(name) = name.to_str() else {
+                eprintln!("skipping non-UTF8 backlight device: {name:?}");
+                return None;
+            };
 
-    Err(Error::EternalDarkness)
+            Some((
+                Backlight {
+                    name: name.to_owned(),
+                    subsystem,
+                    max,
+                    step: 1,
+                    source: BrightnessFile::Brightness,
+                },
+                current,
+            ))
+        }
+        Err(e) => {
+            eprintln!(
+                "skipping backlight-like device at {}: {e}",
+                path.display()
+            );
+            None
+        }
+    }
 }
 
 /// Finds a backlight given a user-specified name.
@@ -106,12 +677,54 @@ pub fn find_first_backlight() -> Result<(Backlight, u32), Error> {
 /// On success, returns both the `Backlight` and its current setting.
 pub fn use_specific_backlight(
     name: impl Into<String>
+) -> Result<(Backlight, u32), Error> {
+    use_specific_device(Subsystem::Backlight, name)
+}
+
+/// Like `use_specific_backlight`, but for a device in any `subsystem`, such
+/// as an LED like `tpacpi::kbd_backlight`.
+///
+/// Like `find_keyboard_backlights`, this warns on stderr about an LED that a
+/// kernel trigger is driving (see `Backlight::trigger`).
+pub fn use_specific_device(
+    subsystem: Subsystem,
+    name: impl Into<String>,
 ) -> Result<(Backlight, u32), Error> {
     let name = name.into();
-    let path = Path::new("/sys/class/backlight").join(&name);
+    let path = subsystem.dir().join(&name);
     let (current, max) = read_backlight_settings(&path)?;
 
-    Ok((Backlight { name, max }, current))
+    let bl = Backlight {
+        name,
+        subsystem,
+        max,
+        step: 1,
+        source: BrightnessFile::Brightness,
+    };
+    warn_about_trigger(&bl);
+    Ok((bl, current))
+}
+
+/// Explains on stderr that a kernel trigger is driving `bl`, if one is, since
+/// the trigger may undo whatever brightness we set.
+fn warn_about_trigger(bl: &Backlight) {
+    if let Ok(Some(trigger)) = bl.trigger() {
+        eprintln!(
+            "warning: kernel trigger {trigger:?} is driving {}, and may \
+             override changes; write \"none\" to its trigger to stop it",
+            bl.name,
+        );
+    }
+}
+
+/// Picks the active trigger out of an LED's `trigger`, which lists every
+/// trigger available with the active one in brackets, like
+/// `none [kbd-backlight] timer`. Returns `None` if that's `none`.
+fn active_trigger(text: &str) -> Option<&str> {
+    let active = text
+        .split_whitespace()
+        .find_map(|t| t.strip_prefix('[')?.strip_suffix(']'))?;
+    (active != "none").then_some(active)
 }
 
 /// Sets the brightness of a `Backlight` given an existing connection to the
@@ -121,16 +734,32 @@ pub fn use_specific_backlight(
 /// If you want to change the backlight only once, the
 /// `connect_and_set_brightness` operation is more convenient.
 ///
-/// # Panics
-///
-/// If `new_value` is out of range for `backlight` (check it against
-/// `backlight.max`).
+/// Returns `Error::OutOfRange` if `new_value` is above `backlight.max`.
 pub fn set_brightness(
     session: &SessionProxyBlocking<'_>,
     backlight: &Backlight,
-    new_value: u32,
+    new_value: impl Into<Brightness>,
 ) -> Result<(), Error> {
-    Ok(session.set_brightness("backlight", &backlight.name, new_value)?)
+    let new_value = new_value.into().to_raw(backlight);
+    check_range(new_value, backlight.max)?;
+    let subsystem = backlight.subsystem.name();
+    Ok(session.set_brightness(subsystem, &backlight.name, new_value)?)
+}
+
+/// Like `set_brightness`, but rather than requiring `new_value` to be in
+/// range, clamps it to `backlight.max` and rounds it to a level the hardware
+/// can really show (see `Backlight::quantize`). Returns the raw value actually
+/// written, so callers can keep their own idea of the brightness in step with
+/// the hardware without reading it back.
+pub fn set_brightness_checked(
+    session: &SessionProxyBlocking<'_>,
+    backlight: &Backlight,
+    new_value: impl Into<Brightness>,
+) -> Result<u32, Error> {
+    let new_value = new_value.into().to_raw(backlight);
+    let applied = backlight.quantize(new_value);
+    set_brightness(session, backlight, applied)?;
+    Ok(applied)
 }
 
 /// Sets the brightness of a `Backlight` given an existing connection to the
@@ -140,40 +769,228 @@ pub fn set_brightness(
 /// If you want to change the backlight only once, the
 /// `connect_and_set_brightness` operation is more convenient.
 ///
-/// # Panics
-///
-/// If `new_value` is out of range for `backlight` (check it against
-/// `backlight.max`).
+/// Returns `Error::OutOfRange` if `new_value` is above `backlight.max`.
 pub async fn async_set_brightness(
     session: &SessionProxy<'_>,
     backlight: &Backlight,
-    new_value: u32,
+    new_value: impl Into<Brightness>,
 ) -> Result<(), Error> {
-    Ok(session.set_brightness("backlight", &backlight.name, new_value).await?)
+    let new_value = new_value.into().to_raw(backlight);
+    check_range(new_value, backlight.max)?;
+    let subsystem = backlight.subsystem.name();
+    Ok(session
+        .set_brightness(subsystem, &backlight.name, new_value)
+        .await?)
+}
+
+/// Like `set_brightness_checked`, but for async code.
+#[cfg(feature = "async")]
+pub async fn async_set_brightness_checked(
+    session: &SessionProxy<'_>,
+    backlight: &Backlight,
+    new_value: impl Into<Brightness>,
+) -> Result<u32, Error> {
+    let new_value = new_value.into().to_raw(backlight);
+    let applied = backlight.quantize(new_value);
+    async_set_brightness(session, backlight, applied).await?;
+    Ok(applied)
 }
 
 /// Connects to the session DBus and logind and changes the brightness of a
 /// given `backlight`.
 ///
-/// # Panics
-///
-/// If `new_value` is out of range for `backlight` (check it against
-/// `backlight.max`).
+/// Returns `Error::OutOfRange` if `new_value` is above `backlight.max`.
 pub fn connect_and_set_brightness(
     backlight: &Backlight,
-    new_value: u32,
+    new_value: impl Into<Brightness>,
 ) -> Result<(), Error> {
-    assert!(new_value <= backlight.max);
+    let new_value = new_value.into().to_raw(backlight);
+    check_range(new_value, backlight.max)?;
+    let session = connect_to_session()?;
+    set_brightness(&session, backlight, new_value)
+}
 
+/// Connects to logind's view of the current session, for use with
+/// `set_brightness`.
+///
+/// Like any zbus program, this finds the system bus through the
+/// `DBUS_SYSTEM_BUS_ADDRESS` environment variable if it's set. See
+/// `logind_destination` for how to talk to something other than logind.
+pub fn connect_to_session() -> Result<SessionProxyBlocking<'static>, Error> {
     // Set up our DBus connection to the current session (.../session/auto).
     // Note that this happens on the SYSTEM bus, _not_ the SESSION bus!
     // This confused me too.
     let conn = Connection::system()?;
-    let session = SessionProxyBlocking::builder(&conn)
+    connect_to_session_on(&conn)
+}
+
+/// Like `connect_to_session`, but uses an existing connection `conn`, which
+/// needn't be to the system bus. This is useful for pointing the library at a
+/// private bus, such as one running a fake logind for testing.
+pub fn connect_to_session_on(
+    conn: &Connection,
+) -> Result<SessionProxyBlocking<'static>, Error> {
+    Ok(SessionProxyBlocking::builder(conn)
+        .destination(logind_destination())?
         .path("/org/freedesktop/login1/session/auto")?
-        .build()?;
+        .build()?)
+}
 
-    set_brightness(&session, backlight, new_value)
+/// Like `connect_and_set_brightness`, but for async code.
+///
+/// Returns `Error::OutOfRange` if `new_value` is above `backlight.max`.
+#[cfg(feature = "async")]
+pub async fn async_connect_and_set_brightness(
+    backlight: &Backlight,
+    new_value: impl Into<Brightness>,
+) -> Result<(), Error> {
+    let new_value = new_value.into().to_raw(backlight);
+    check_range(new_value, backlight.max)?;
+    let session = async_connect_to_session().await?;
+    async_set_brightness(&session, backlight, new_value).await
+}
+
+/// Like `connect_to_session`, but for async code, giving a session for use
+/// with `async_set_brightness`. Like the rest of zbus, this doesn't need any
+/// particular async runtime.
+///
+/// Finding devices only involves reading sysfs, which never waits on
+/// hardware, so there are no async versions of `find_first_backlight` and
+/// friends; they're fine to call from async code as they are.
+#[cfg(feature = "async")]
+pub async fn async_connect_to_session() -> Result<SessionProxy<'static>, Error>
+{
+    let conn = zbus::Connection::system().await?;
+    async_connect_to_session_on(&conn).await
+}
+
+/// Like `connect_to_session_on`, but for async code.
+#[cfg(feature = "async")]
+pub async fn async_connect_to_session_on(
+    conn: &zbus::Connection,
+) -> Result<SessionProxy<'static>, Error> {
+    Ok(SessionProxy::builder(conn)
+        .destination(logind_destination())?
+        .path("/org/freedesktop/login1/session/auto")?
+        .build()
+        .await?)
+}
+
+/// Returns the bus name that session requests are sent to. This is normally
+/// logind's, `org.freedesktop.login1`, but if the `BRIGHTR_LOGIND` environment
+/// variable is set, its value is used instead. Like `BRIGHTR_SYSFS`, that's
+/// mostly useful for testing against a fake logind that can't take the real
+/// name.
+pub fn logind_destination() -> String {
+    std::env::var("BRIGHTR_LOGIND")
+        .ok()
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "org.freedesktop.login1".to_owned())
+}
+
+/// The logind session that brightness changes are sent to, as logind sees it.
+#[derive(Clone, Debug)]
+pub struct SessionInfo {
+    /// logind's ID for the session, like `3` or `c1`.
+    pub id: String,
+    /// The seat the session is attached to, like `seat0`, if any. Sessions
+    /// without a seat (over SSH, say) can't change the brightness.
+    pub seat: Option<String>,
+    /// Numeric ID of the user who owns the session.
+    pub uid: u32,
+    /// Name of the user who owns the session.
+    pub user: String,
+}
+
+/// Asks logind which session `session` actually refers to. The connection
+/// made by `connect_to_session` asks for the caller's own session, without
+/// saying which that is, so this is useful for diagnostics and bug reports.
+pub fn session_info(
+    session: &SessionProxyBlocking<'_>,
+) -> Result<SessionInfo, Error> {
+    // Seat and User are each a name or number paired with an object path,
+    // which we have no use for.
+    let (seat, _) = session.seat()?;
+    let (uid, _) = session.user()?;
+    Ok(SessionInfo {
+        id: session.id()?,
+        seat: Some(seat).filter(|s| !s.is_empty()),
+        uid,
+        user: session.name()?,
+    })
+}
+
+/// Sets the brightness of a `Backlight` by writing to sysfs directly, rather
+/// than going through logind. This only works if the current user can write
+/// to the device's `brightness` file, which usually means running as root or
+/// installing a udev rule; it's mostly useful where logind isn't available.
+///
+/// Returns `Error::OutOfRange` if `new_value` is above `backlight.max`.
+pub fn sysfs_set_brightness(
+    backlight: &Backlight,
+    new_value: impl Into<Brightness>,
+) -> Result<(), Error> {
+    let new_value = new_value.into().to_raw(backlight);
+    check_range(new_value, backlight.max)?;
+    let path = backlight.path().join("brightness");
+    fs::write(&path, new_value.to_string())
+        .map_err(|e| Error::Access(path.display().to_string(), e))
+}
+
+/// Switches `backlight` fully on or off, through its `bl_power`, by writing
+/// to sysfs directly. This cuts the backlight completely, which setting the
+/// brightness to 0 doesn't on every panel. logind can only change the
+/// brightness, so unlike `set_brightness`, this needs write access to the
+/// device's `bl_power` file, which usually means running as root or
+/// installing a udev rule.
+///
+/// Returns `Error::NoPowerControl` if the device has no `bl_power`.
+pub fn sysfs_set_power(backlight: &Backlight, on: bool) -> Result<(), Error> {
+    let path = backlight.path().join("bl_power");
+    // These are the framebuffer blanking levels "unblank" and "powerdown".
+    let value = if on { "0" } else { "4" };
+    // Opening without creating, so a missing file is reported as one here,
+    // as it is by sysfs.
+    let result = fs::OpenOptions::new()
+        .write(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(value.as_bytes()));
+    match result {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            Err(Error::NoPowerControl(backlight.name.clone()))
+        }
+        Err(e) => Err(Error::Access(path.display().to_string(), e)),
+    }
+}
+
+/// Changes the colors of a multicolor LED, by writing `intensities`, one for
+/// each of the colors from `Backlight::read_colors`, in the same order, to
+/// its `multi_intensity` in sysfs. Like `sysfs_set_power`, this needs write
+/// access to the file, since logind can only change the brightness. The
+/// brightness stays as it was, and scales the new colors.
+///
+/// Returns `Error::OutOfRange` if any intensity is above `backlight.max`.
+pub fn sysfs_set_colors(
+    backlight: &Backlight,
+    intensities: &[u32],
+) -> Result<(), Error> {
+    for &intensity in intensities {
+        check_range(intensity, backlight.max)?;
+    }
+    let text = intensities
+        .iter()
+        .map(u32::to_string)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let path = backlight.path().join("multi_intensity");
+    // The kernel rejects the wrong number of intensities, and, unlike a
+    // plain write, opening without creating reports a missing file as one.
+    fs::OpenOptions::new()
+        .write(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(text.as_bytes()))
+        .map_err(|e| Error::Access(path.display().to_string(), e))
 }
 
 /// Loads settings for a single backlight device given its fully-qualified
@@ -184,14 +1001,145 @@ fn read_backlight_settings(path: &Path) -> Result<(u32, u32), Error> {
         let c_path = path.join(component);
         let contents = fs::read_to_string(&c_path)
             .map_err(|e| Error::Access(c_path.display().to_string(), e))?;
-        let number = contents.trim().parse::<u32>().map_err(|e| {
-            Error::Parsing(
-                c_path.display().to_string(),
-                contents.trim().to_string(),
-                e,
-            )
-        })?;
-        parsed.push(number);
-    }
-    Ok((parsed[0], parsed[1]))
+        parsed.push(parse_attribute(&c_path, &contents)?);
+    }
+    // Some drivers report a brightness above their own maximum, at least for a
+    // while after boot. Callers assume it's in range, so make it so.
+    Ok((parsed[0].min(parsed[1]), parsed[1]))
+}
+
+/// Parses the `contents` of the sysfs attribute at `path`. This should be a
+/// decimal number and a newline, but real drivers have been seen to get this
+/// wrong in various ways. We ignore anything after the leading digits, and
+/// saturate values too big for a `u32`.
+fn parse_attribute(path: &Path, contents: &str) -> Result<u32, Error> {
+    let text = contents.trim();
+    if text.is_empty() {
+        return Err(Error::Empty(path.display().to_string()));
+    }
+    let end = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    // If there are no digits at all, parsing the whole thing gets us a more
+    // sensible error than parsing nothing.
+    let digits = if end == 0 { text } else { &text[..end] };
+    match digits.parse::<u32>() {
+        Ok(number) => Ok(number),
+        Err(e) if *e.kind() == IntErrorKind::PosOverflow => Ok(u32::MAX),
+        Err(e) => Err(Error::Parsing(
+            path.display().to_string(),
+            text.to_string(),
+            e,
+        )),
+    }
+}
+
+/// Pairs up the color names in `index`, from `multi_index`, with the
+/// `intensities` read from `multi_intensity` at `path`.
+fn parse_colors(
+    path: &Path,
+    index: &str,
+    intensities: &str,
+) -> Result<Vec<ColorChannel>, Error> {
+    let colors = index.split_whitespace().collect::<Vec<_>>();
+    let intensities = intensities
+        .split_whitespace()
+        .map(|text| parse_attribute(path, text))
+        .collect::<Result<Vec<_>, _>>()?;
+    if colors.len() != intensities.len() {
+        let e = io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} intensities for {} colors",
+                intensities.len(),
+                colors.len()
+            ),
+        );
+        return Err(Error::Access(path.display().to_string(), e));
+    }
+    Ok(colors
+        .into_iter()
+        .zip(intensities)
+        .map(|(color, intensity)| ColorChannel {
+            color: color.to_string(),
+            intensity,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_pair_up() {
+        let path = Path::new("multi_intensity");
+        assert_eq!(
+            parse_colors(path, "red green blue\n", "255 128 0\n").unwrap(),
+            [("red", 255), ("green", 128), ("blue", 0)].map(
+                |(color, intensity)| ColorChannel {
+                    color: color.to_string(),
+                    intensity,
+                }
+            ),
+        );
+        assert!(parse_colors(path, "red green blue\n", "255 128\n").is_err());
+        assert!(parse_colors(path, "red\n", "x\n").is_err());
+    }
+
+    #[test]
+    fn active_trigger_is_bracketed() {
+        assert_eq!(
+            active_trigger("none [kbd-backlight] timer\n"),
+            Some("kbd-backlight"),
+        );
+        assert_eq!(active_trigger("[none] kbd-backlight timer\n"), None);
+        assert_eq!(active_trigger("none timer\n"), None);
+        assert_eq!(active_trigger(""), None);
+    }
+
+    /// A device with raw range 0 to `max`, whose hardware moves in `step`s.
+    fn device(max: u32, step: u32) -> Backlight {
+        Backlight {
+            name: "test".to_string(),
+            subsystem: Subsystem::Backlight,
+            max,
+            step,
+            source: BrightnessFile::Brightness,
+        }
+    }
+
+    #[test]
+    fn quantize_rounds_to_steps() {
+        let bl = device(1000, 250);
+        assert_eq!(bl.quantize(0), 0);
+        assert_eq!(bl.quantize(124), 0);
+        assert_eq!(bl.quantize(125), 250);
+        assert_eq!(bl.quantize(600), 500);
+        // Values beyond the range are clamped first.
+        assert_eq!(bl.quantize(5000), 1000);
+    }
+
+    #[test]
+    fn quantize_near_max() {
+        // 1000 isn't a multiple of 300, but is a level all the same.
+        let bl = device(1000, 300);
+        assert_eq!(bl.quantize(940), 900);
+        assert_eq!(bl.quantize(950), 1000);
+        assert_eq!(bl.quantize(999), 1000);
+        assert_eq!(bl.quantize(1000), 1000);
+    }
+
+    #[test]
+    fn quantize_odd_steps() {
+        // A step of 0 is taken as 1, so everything is a level.
+        let bl = device(100, 0);
+        assert_eq!(bl.quantize(37), 37);
+        assert_eq!(bl.quantize(100), 100);
+        // A step bigger than the range leaves just off and full.
+        let bl = device(100, 500);
+        assert_eq!(bl.quantize(49), 0);
+        assert_eq!(bl.quantize(50), 100);
+        assert_eq!(bl.quantize(u32::MAX), 100);
+    }
 }
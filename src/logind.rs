@@ -0,0 +1,45 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The part of logind's session interface that we use.
+//!
+//! This is all that's needed of logind, so it's written out here rather than
+//! taken from a crate covering the whole of logind.
+
+use zbus::zvariant::OwnedObjectPath;
+
+/// A logind session, which can change the brightness of backlights on its
+/// seat. `connect_to_session` and friends connect to the caller's own.
+#[zbus::proxy(
+    interface = "org.freedesktop.login1.Session",
+    default_service = "org.freedesktop.login1"
+)]
+pub trait Session {
+    /// Sets device `name`, in kernel subsystem `subsystem`, to raw value
+    /// `brightness`.
+    fn set_brightness(
+        &self,
+        subsystem: &str,
+        name: &str,
+        brightness: u32,
+    ) -> zbus::Result<()>;
+
+    /// logind's ID for the session, like `3` or `c1`.
+    #[zbus(property)]
+    fn id(&self) -> zbus::Result<String>;
+
+    /// Name of the user who owns the session.
+    #[zbus(property)]
+    fn name(&self) -> zbus::Result<String>;
+
+    /// The seat the session is attached to, or an empty name for none, along
+    /// with the seat's object.
+    #[zbus(property)]
+    fn seat(&self) -> zbus::Result<(String, OwnedObjectPath)>;
+
+    /// Numeric ID of the user who owns the session, along with the user's
+    /// object.
+    #[zbus(property)]
+    fn user(&self) -> zbus::Result<(u32, OwnedObjectPath)>;
+}
@@ -0,0 +1,89 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Keyboard backlight control through UPower.
+//!
+//! On many laptops, UPower knows about the keyboard backlight and will adjust
+//! it for unprivileged users, much like logind does for displays. It also
+//! signals whenever the brightness changes, including changes made by firmware
+//! hotkeys, which saves polling.
+
+use crate::Error;
+use proxy::UpowerKbdProxyBlocking;
+use zbus::blocking::Connection;
+
+/// UPower's keyboard backlight interface. This lives in its own module so that
+/// the generated signal types don't need documenting.
+#[allow(missing_docs)]
+mod proxy {
+    #[zbus::proxy(
+        interface = "org.freedesktop.UPower.KbdBacklight",
+        default_service = "org.freedesktop.UPower",
+        default_path = "/org/freedesktop/UPower/KbdBacklight"
+    )]
+    trait UpowerKbd {
+        /// Gets the current raw brightness.
+        fn get_brightness(&self) -> zbus::Result<i32>;
+        /// Gets the highest raw brightness, or 0 if there's no keyboard
+        /// backlight.
+        fn get_max_brightness(&self) -> zbus::Result<i32>;
+        /// Sets the raw brightness.
+        fn set_brightness(&self, value: i32) -> zbus::Result<()>;
+        /// Emitted when the brightness changes for any reason.
+        #[zbus(signal)]
+        fn brightness_changed(&self, value: i32) -> zbus::Result<()>;
+    }
+}
+
+/// A connection to UPower's keyboard backlight control.
+#[derive(Clone, Debug)]
+pub struct KbdBacklight {
+    proxy: UpowerKbdProxyBlocking<'static>,
+}
+
+impl KbdBacklight {
+    /// Connects to UPower. This succeeds even if there's no keyboard backlight;
+    /// you'll find out when you call `max_brightness`.
+    pub fn connect() -> Result<Self, Error> {
+        // Like logind, this is on the SYSTEM bus.
+        let conn = Connection::system()?;
+        Ok(Self {
+            proxy: UpowerKbdProxyBlocking::new(&conn)?,
+        })
+    }
+
+    /// Gets the current raw brightness.
+    pub fn brightness(&self) -> Result<u32, Error> {
+        let value = self.proxy.get_brightness()?;
+        u32::try_from(value).map_err(|_| Error::EternalDarkness)
+    }
+
+    /// Gets the highest raw brightness the keyboard backlight supports.
+    ///
+    /// Returns `Error::EternalDarkness` if UPower didn't find a keyboard
+    /// backlight.
+    pub fn max_brightness(&self) -> Result<u32, Error> {
+        match u32::try_from(self.proxy.get_max_brightness()?) {
+            Ok(max) if max > 0 => Ok(max),
+            _ => Err(Error::EternalDarkness),
+        }
+    }
+
+    /// Sets the raw brightness. UPower will reject values above
+    /// `max_brightness`.
+    pub fn set_brightness(&self, new_value: u32) -> Result<(), Error> {
+        let new_value = i32::try_from(new_value).unwrap_or(i32::MAX);
+        Ok(self.proxy.set_brightness(new_value)?)
+    }
+
+    /// Returns an iterator that blocks waiting for the brightness to change,
+    /// and then produces the new raw value.
+    pub fn changes(&self) -> Result<impl Iterator<Item = u32>, Error> {
+        let signals = self.proxy.receive_brightness_changed()?;
+        Ok(signals.filter_map(|signal| {
+            let value = signal.args().ok()?.value;
+            u32::try_from(value).ok()
+        }))
+    }
+}
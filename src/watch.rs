@@ -0,0 +1,152 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Waiting for a backlight's brightness to change, using inotify, rather than
+//! checking it over and over.
+//!
+//! This is only available on Linux, with the `watch` feature.
+//!
+//! The kernel reports a change whenever a program writes the device's
+//! `brightness`, and whenever the driver says the hardware changed by itself,
+//! which most do for changes made by the firmware, like function keys it
+//! handles. LEDs, like keyboard backlights, announce changes made by the
+//! hardware through `brightness_hw_changed` instead, where they have it.
+//! Drivers that don't say are rare, but for those, the async `events::watch`
+//! checks the device regularly instead.
+
+use crate::{Backlight, Error};
+use rustix::{
+    event::{poll, PollFd, PollFlags},
+    fs::inotify,
+    io::Errno,
+};
+use std::{
+    mem::MaybeUninit,
+    os::fd::{AsFd, BorrowedFd, OwnedFd},
+};
+
+/// Waits for changes to a backlight's brightness. As an `Iterator`, this
+/// blocks until the brightness changes, and then produces the new raw value;
+/// it ends if the device goes away.
+///
+/// Programs with their own event loop can instead wait for the file
+/// descriptor from `as_fd` to become readable, and then call `check`, which
+/// never blocks.
+#[derive(Debug)]
+pub struct Watcher {
+    backlight: Backlight,
+    inotify: OwnedFd,
+    buffer: Box<[MaybeUninit<u8>]>,
+    /// Raw value last reported, so that we only report real changes.
+    last: Option<u32>,
+    /// Set when the device goes away, to end the iterator.
+    gone: bool,
+}
+
+impl Backlight {
+    /// Starts watching this device for changes to its brightness, made by
+    /// any program or by the firmware. See `Watcher`.
+    pub fn watch(&self) -> Result<Watcher, Error> {
+        let access = |e| access_error(self, e);
+        let flags =
+            inotify::CreateFlags::CLOEXEC | inotify::CreateFlags::NONBLOCK;
+        let inotify = inotify::init(flags).map_err(access)?;
+        let attrs =
+            ["brightness", "actual_brightness", "brightness_hw_changed"];
+        for attr in attrs {
+            let result = inotify::add_watch(
+                &inotify,
+                self.path().join(attr),
+                inotify::WatchFlags::MODIFY,
+            );
+            match result {
+                Ok(_) => (),
+                // Some devices, like LEDs, have no `actual_brightness`, and
+                // only some LEDs have `brightness_hw_changed`.
+                Err(Errno::NOENT) if attr != "brightness" => (),
+                Err(e) => return Err(access(e)),
+            }
+        }
+        Ok(Watcher {
+            backlight: self.clone(),
+            inotify,
+            buffer: vec![MaybeUninit::uninit(); 4096].into_boxed_slice(),
+            last: self.read_current().ok(),
+            gone: false,
+        })
+    }
+}
+
+impl Watcher {
+    /// Takes whatever changes the kernel has reported since last time,
+    /// without waiting, and returns the new raw value if the brightness is
+    /// different from the last one reported.
+    pub fn check(&mut self) -> Result<Option<u32>, Error> {
+        let mut reader = inotify::Reader::new(&self.inotify, &mut self.buffer);
+        let mut reported = false;
+        loop {
+            match reader.next() {
+                Ok(event) => {
+                    reported = true;
+                    if event.events().contains(inotify::ReadFlags::IGNORED) {
+                        self.gone = true;
+                    }
+                }
+                Err(Errno::AGAIN) => break,
+                Err(e) => return Err(access_error(&self.backlight, e)),
+            }
+        }
+        if !reported || self.gone {
+            return Ok(None);
+        }
+        match self.backlight.read_current() {
+            Ok(now) if Some(now) == self.last => Ok(None),
+            Ok(now) => {
+                self.last = Some(now);
+                Ok(Some(now))
+            }
+            // Caught part way through a write; the end of the write will be
+            // along in a moment.
+            Err(Error::Empty(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Checks whether the device has gone away, which ends the watch.
+    pub fn is_gone(&self) -> bool {
+        self.gone
+    }
+}
+
+impl Iterator for Watcher {
+    type Item = Result<u32, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.gone {
+            match self.check() {
+                Ok(Some(now)) => return Some(Ok(now)),
+                Ok(None) if self.gone => break,
+                Ok(None) => (),
+                Err(e) => return Some(Err(e)),
+            }
+            let mut fds = [PollFd::new(&self.inotify, PollFlags::IN)];
+            match poll(&mut fds, None) {
+                Ok(_) | Err(Errno::INTR) => (),
+                Err(e) => return Some(Err(access_error(&self.backlight, e))),
+            }
+        }
+        None
+    }
+}
+
+/// Wraps error `e`, from watching `backlight`.
+fn access_error(backlight: &Backlight, e: Errno) -> Error {
+    Error::Access(backlight.path().display().to_string(), e.into())
+}
+
+impl AsFd for Watcher {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inotify.as_fd()
+    }
+}
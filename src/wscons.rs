@@ -0,0 +1,55 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Display brightness on OpenBSD, through wscons.
+//!
+//! The kernel exposes the brightness through ioctls on the console device, but
+//! calling those would need `unsafe`, which this crate forbids, so we go
+//! through `wsconsctl(8)` instead. That costs a process per call, which is
+//! fine for a key press, but too slow for a smooth fade. Either way, this needs
+//! permission to open the console device, which usually means running as root.
+//!
+//! wscons only deals in percentages, so there's no notion of raw units here.
+
+use crate::Error;
+use std::{io, process::Command};
+
+/// Name of the wscons variable holding the display brightness.
+const VAR: &str = "display.brightness";
+
+/// Reads the display brightness, in percent.
+pub fn get_brightness() -> Result<u32, Error> {
+    let text = wsconsctl(&["-n", VAR])?;
+    // This comes back with two decimal places and a percent sign, like
+    // `50.00%`. We only care about the whole part.
+    let whole = text.trim_end_matches('%').split('.').next().unwrap_or("");
+    whole
+        .parse::<u32>()
+        .map_err(|e| Error::Parsing("wsconsctl".to_string(), text.clone(), e))
+}
+
+/// Sets the display brightness, in percent.
+///
+/// Returns `Error::OutOfRange` if `percent` is more than 100.
+pub fn set_brightness(percent: u32) -> Result<(), Error> {
+    crate::check_range(percent, 100)?;
+
+    wsconsctl(&[&format!("{VAR}={percent}")])?;
+    Ok(())
+}
+
+/// Runs `wsconsctl` with `args`, returning its output minus any trailing
+/// newline.
+fn wsconsctl(args: &[&str]) -> Result<String, Error> {
+    let access = |e| Error::Access("wsconsctl".to_string(), e);
+    let output = Command::new("wsconsctl")
+        .args(args)
+        .output()
+        .map_err(access)?;
+    if !output.status.success() {
+        let msg = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(access(io::Error::other(msg)));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -0,0 +1,1407 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! End-to-end tests of the command line tool, run against a fake sysfs tree
+//! and a fake logind on a private DBus, so they need no backlight hardware and
+//! never touch the real devices. They do need `dbus-daemon`; without it, each
+//! test passes without doing anything, and says so once on stderr.
+//!
+//! The tool is an example, so these expect it to have been built already, which
+//! a plain `cargo test` does.
+
+use std::{
+    env, fs,
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, Output, Stdio},
+    sync::Once,
+    thread,
+    time::Duration,
+};
+use tempfile::TempDir;
+use zbus::zvariant::OwnedObjectPath;
+
+/// A fake system: a sysfs tree holding backlight devices, and a DBus with a
+/// fake logind that changes them.
+struct Fake {
+    dir: TempDir,
+    bus: Child,
+    address: String,
+    logind: zbus::blocking::Connection,
+}
+
+impl Fake {
+    /// Sets up a fake system with the given backlight devices, each given as
+    /// `(name, brightness, max_brightness)`, or returns `None` if there's no
+    /// `dbus-daemon` to run the fake logind on, so the test should be skipped.
+    fn new(devices: &[(&str, u32, u32)]) -> Option<Self> {
+        let dir = TempDir::new().expect("can't create temp dir");
+
+        let mut bus = match Command::new("dbus-daemon")
+            .arg("--session")
+            .arg("--nofork")
+            .arg("--print-address")
+            .arg(format!("--address=unix:dir={}", dir.path().display()))
+            .stdout(Stdio::piped())
+            // It complains about things like fd limits, which we don't care
+            // about.
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(bus) => bus,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                // The test harness captures eprintln!, but not this.
+                static NOTICE: Once = Once::new();
+                NOTICE.call_once(|| {
+                    let _ = io::stderr().write_all(
+                        b"dbus-daemon isn't installed, so the end-to-end \
+                          tests are being skipped\n",
+                    );
+                });
+                return None;
+            }
+            Err(e) => panic!("can't start dbus-daemon: {e}"),
+        };
+        let mut address = String::new();
+        BufReader::new(bus.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        let address = address.trim().to_string();
+
+        let logind = zbus::blocking::connection::Builder::address(&*address)
+            .unwrap()
+            .name("org.freedesktop.login1")
+            .unwrap()
+            .serve_at(
+                "/org/freedesktop/login1/session/auto",
+                Session {
+                    sysfs: dir.path().join("sys"),
+                },
+            )
+            .unwrap()
+            .build()
+            .expect("can't start fake logind");
+
+        let fake = Fake {
+            dir,
+            bus,
+            address,
+            logind,
+        };
+        for &(name, brightness, max) in devices {
+            fake.add_device(name, brightness, max);
+        }
+        Some(fake)
+    }
+
+    /// Adds a backlight device, as if it had just been plugged in.
+    fn add_device(&self, name: &str, brightness: u32, max: u32) {
+        let device = self.dir.path().join("sys/class/backlight").join(name);
+        fs::create_dir_all(&device).unwrap();
+        fs::write(device.join("brightness"), format!("{brightness}\n"))
+            .unwrap();
+        fs::write(device.join("max_brightness"), format!("{max}\n")).unwrap();
+    }
+
+    /// Gives the fake system a battery, reported by a fake UPower, with
+    /// `percentage` charge left, which it's running on if `discharging`.
+    fn battery(&self, percentage: f64, discharging: bool) {
+        let server = self.logind.object_server();
+        let path = "/org/freedesktop/UPower/devices/DisplayDevice";
+        // The first battery brings UPower along; later ones replace it.
+        if server.remove::<Battery, _>(path).is_err() {
+            self.logind.request_name("org.freedesktop.UPower").unwrap();
+        }
+        let battery = Battery {
+            percentage,
+            discharging,
+        };
+        server.at(path, battery).unwrap();
+    }
+
+    /// Overwrites attribute `attr` of device `name` with `text`.
+    fn attr(&self, name: &str, attr: &str, text: &str) {
+        let path = self.dir.path().join("sys/class/backlight").join(name);
+        fs::write(path.join(attr), text).unwrap();
+    }
+
+    /// Makes device `name` act like a driver that accepts writes, but leaves
+    /// the hardware, and so its `actual_brightness`, where it was.
+    fn ignore_writes(&self, name: &str) {
+        self.attr(name, "ignores_writes", "");
+    }
+
+    /// Writes `text` to the config file used by `brightr`.
+    fn config(&self, text: &str) {
+        fs::write(self.dir.path().join("config.toml"), text).unwrap();
+    }
+
+    /// Builds a command to run the tool with `args` against the fake system.
+    fn command(&self, args: &[&str]) -> Command {
+        self.command_as(&brightr_path(), args)
+    }
+
+    /// Like `command`, but runs `program`, which may be another tool or a
+    /// link to this one.
+    fn command_as(&self, program: &Path, args: &[&str]) -> Command {
+        let mut cmd = Command::new(program);
+        cmd.args(args)
+            .env("BRIGHTR_SYSFS", self.dir.path().join("sys"))
+            .env("BRIGHTR_CONFIG", self.dir.path().join("config.toml"))
+            .env("BRIGHTR_SYSTEM_CONFIG", self.dir.path().join("system.toml"))
+            .env("DBUS_SYSTEM_BUS_ADDRESS", &self.address)
+            .env("XDG_RUNTIME_DIR", self.dir.path())
+            .env("XDG_STATE_HOME", self.dir.path().join("state"))
+            .env_remove("RUST_LOG")
+            .env_remove("SSH_CONNECTION")
+            .env_remove("DISPLAY")
+            .env_remove("container");
+        cmd
+    }
+
+    /// Runs the tool with `args` against the fake system.
+    fn brightr(&self, args: &[&str]) -> Output {
+        self.command(args).output().expect("can't run brightr")
+    }
+
+    /// Like `brightr`, but feeds `input` to stdin.
+    fn brightr_with_input(&self, args: &[&str], input: &str) -> Output {
+        let mut child = self
+            .command(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("can't run brightr");
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    /// Like `brightr`, but expects success, and returns stdout.
+    fn ok(&self, args: &[&str]) -> String {
+        let out = self.brightr(args);
+        assert!(
+            out.status.success(),
+            "brightr {args:?} failed:\n{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+        String::from_utf8(out.stdout).unwrap()
+    }
+
+    /// Reads the raw brightness of device `name`.
+    fn brightness(&self, name: &str) -> u32 {
+        let path = self
+            .dir
+            .path()
+            .join("sys/class/backlight")
+            .join(name)
+            .join("brightness");
+        fs::read_to_string(path).unwrap().trim().parse().unwrap()
+    }
+}
+
+impl Drop for Fake {
+    fn drop(&mut self) {
+        self.bus.kill().ok();
+        self.bus.wait().ok();
+    }
+}
+
+/// Just enough of logind's session interface for brightr.
+struct Session {
+    sysfs: PathBuf,
+}
+
+#[zbus::interface(name = "org.freedesktop.login1.Session")]
+impl Session {
+    /// Writes the new setting straight to the device, as logind does. Like a
+    /// real driver, the device's `actual_brightness`, if it has one, follows,
+    /// unless the device has been told to ignore writes.
+    fn set_brightness(
+        &self,
+        subsystem: &str,
+        name: &str,
+        brightness: u32,
+    ) -> zbus::fdo::Result<()> {
+        let device = self.sysfs.join("class").join(subsystem).join(name);
+        let write = |attr| {
+            fs::write(device.join(attr), brightness.to_string())
+                .map_err(|e| zbus::fdo::Error::Failed(e.to_string()))
+        };
+        write("brightness")?;
+        let actual = device.join("actual_brightness");
+        if actual.exists() && !device.join("ignores_writes").exists() {
+            write("actual_brightness")?;
+        }
+        Ok(())
+    }
+
+    #[zbus(property)]
+    fn id(&self) -> String {
+        "3".to_string()
+    }
+
+    #[zbus(property)]
+    fn seat(&self) -> (String, OwnedObjectPath) {
+        let path = "/org/freedesktop/login1/seat/seat0";
+        ("seat0".to_string(), path.try_into().unwrap())
+    }
+
+    #[zbus(property)]
+    fn user(&self) -> (u32, OwnedObjectPath) {
+        let path = "/org/freedesktop/login1/user/_1000";
+        (1000, path.try_into().unwrap())
+    }
+
+    #[zbus(property)]
+    fn name(&self) -> String {
+        "alice".to_string()
+    }
+}
+
+/// Just enough of UPower's display device for brightr.
+struct Battery {
+    percentage: f64,
+    discharging: bool,
+}
+
+#[zbus::interface(name = "org.freedesktop.UPower.Device")]
+impl Battery {
+    #[zbus(property)]
+    fn is_present(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn percentage(&self) -> f64 {
+        self.percentage
+    }
+
+    /// UPower's state: 2 for discharging, or 1 for charging.
+    #[zbus(property)]
+    fn state(&self) -> u32 {
+        if self.discharging {
+            2
+        } else {
+            1
+        }
+    }
+}
+
+/// Finds the built tool, which lives next to the directory holding this test.
+fn brightr_path() -> PathBuf {
+    let exe = env::current_exe().unwrap();
+    let profile = exe.parent().and_then(Path::parent).unwrap();
+    profile.join("examples").join("brightr")
+}
+
+#[test]
+fn mini_cli() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    let mini = brightr_path().with_file_name("brightr-mini");
+    let run = |args: &[&str]| fake.command_as(&mini, args).output().unwrap();
+    assert_eq!(run(&["get"]).stdout, b"30/100\n");
+    assert!(run(&["set", "50"]).status.success());
+    assert_eq!(fake.brightness("intel_backlight"), 500);
+    assert!(run(&["-n", "intel_backlight", "up", "10"]).status.success());
+    assert_eq!(fake.brightness("intel_backlight"), 600);
+    assert!(run(&["-r", "down", "650"]).status.success());
+    assert_eq!(fake.brightness("intel_backlight"), 0);
+    assert!(run(&["set", "100r"]).status.success());
+    assert_eq!(run(&["-r", "get"]).stdout, b"100/1000\n");
+    assert!(!run(&["frob"]).status.success());
+    assert!(!run(&["set"]).status.success());
+}
+
+#[test]
+fn get_finds_device() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    assert_eq!(fake.ok(&["get"]), "30/100\n");
+    assert_eq!(fake.ok(&["-r", "get"]), "300/1000\n");
+}
+
+#[test]
+fn get_exit_status() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    let status = |args: &[&str]| fake.brightr(args).status.code().unwrap();
+    assert_eq!(status(&["get", "--exit-status"]), 0);
+    assert_eq!(status(&["-m", "300", "get", "--exit-status"]), 4);
+    fake.ok(&["set", "100"]);
+    assert_eq!(status(&["get", "--exit-status"]), 3);
+    assert_eq!(status(&["get"]), 0);
+}
+
+#[test]
+fn get_on_off_device() {
+    let Some(fake) = Fake::new(&[("pwm_backlight", 1, 1)]) else {
+        return;
+    };
+    assert_eq!(fake.ok(&["get"]), "on\n");
+    assert_eq!(fake.ok(&["get", "--bare"]), "on\n");
+    fake.ok(&["down", "10"]);
+    assert_eq!(fake.ok(&["get"]), "off\n");
+    assert_eq!(fake.ok(&["-r", "get"]), "0/1\n");
+}
+
+#[test]
+fn set_up_and_down() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    fake.ok(&["set", "80"]);
+    assert_eq!(fake.brightness("intel_backlight"), 800);
+    fake.ok(&["down", "25"]);
+    assert_eq!(fake.brightness("intel_backlight"), 550);
+    fake.ok(&["up", "100r"]);
+    assert_eq!(fake.brightness("intel_backlight"), 650);
+    // Going past the top saturates, unless we're picky.
+    fake.ok(&["up", "50"]);
+    assert_eq!(fake.brightness("intel_backlight"), 1000);
+    assert!(!fake.brightr(&["-p", "up", "10"]).status.success());
+}
+
+#[test]
+fn smooth_fades() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    let start = std::time::Instant::now();
+    // Progress is only shown on a terminal.
+    assert_eq!(fake.ok(&["--smooth=200", "set", "80"]), "");
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    assert_eq!(fake.brightness("intel_backlight"), 800);
+    // Without a number, the next word is still the command.
+    fake.ok(&["--smooth", "down", "10"]);
+    assert_eq!(fake.brightness("intel_backlight"), 700);
+
+    // The config file can make every change fade, unless told otherwise.
+    fake.config("smooth = 200\n");
+    let start = std::time::Instant::now();
+    fake.ok(&["up", "10"]);
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    assert_eq!(fake.brightness("intel_backlight"), 800);
+    fake.ok(&["--instant", "set", "50"]);
+    assert_eq!(fake.brightness("intel_backlight"), 500);
+    assert!(!fake
+        .brightr(&["--instant", "--smooth", "up", "1"])
+        .status
+        .success());
+}
+
+#[test]
+fn picky_notices_ignored_writes() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    fake.ok(&["--picky", "up", "10"]);
+    assert_eq!(fake.brightness("intel_backlight"), 400);
+    fake.attr("intel_backlight", "actual_brightness", "400\n");
+    fake.ignore_writes("intel_backlight");
+    fake.ok(&["up", "10"]);
+    let out = fake.brightr(&["--picky", "up", "10"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("ignoring it"));
+}
+
+#[test]
+fn actual_reads_hardware() {
+    let Some(fake) = Fake::new(&[("amdgpu_bl0", 300, 1000)]) else {
+        return;
+    };
+    fake.attr("amdgpu_bl0", "actual_brightness", "250\n");
+    assert_eq!(fake.ok(&["-r", "get"]), "300/1000\n");
+    assert_eq!(fake.ok(&["--actual", "-r", "get"]), "250/1000\n");
+    fake.ok(&["--actual", "up", "10r"]);
+    assert_eq!(fake.brightness("amdgpu_bl0"), 260);
+
+    // Readings out of range count as the top of it, however they're read.
+    fake.attr("amdgpu_bl0", "actual_brightness", "1500\n");
+    assert_eq!(fake.ok(&["--actual", "-r", "get"]), "1000/1000\n");
+    fake.ignore_writes("amdgpu_bl0");
+    let out = fake.brightr(&["test-device"]);
+    let out = String::from_utf8_lossy(&out.stdout);
+    assert!(out.contains("\nreadback: 1000\n"), "{out}");
+}
+
+#[test]
+fn off_and_on() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    let out = fake.brightr(&["off"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("no bl_power"));
+    fake.attr("intel_backlight", "bl_power", "0\n");
+    let device = fake.dir.path().join("sys/class/backlight/intel_backlight");
+    let bl_power = || {
+        let text = fs::read_to_string(device.join("bl_power")).unwrap();
+        text.trim().to_string()
+    };
+    fake.ok(&["off"]);
+    assert_eq!(bl_power(), "4");
+    assert_eq!(fake.brightness("intel_backlight"), 300);
+    fake.ok(&["on"]);
+    assert_eq!(bl_power(), "0");
+}
+
+#[test]
+fn relative_to_current() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    fake.ok(&["--relative", "current", "up", "10"]);
+    assert_eq!(fake.brightness("intel_backlight"), 330);
+    fake.ok(&["--relative", "current", "down", "50"]);
+    assert_eq!(fake.brightness("intel_backlight"), 165);
+    // Raw steps aren't percentages of anything.
+    fake.ok(&["--relative", "current", "up", "35r"]);
+    assert_eq!(fake.brightness("intel_backlight"), 200);
+}
+
+#[test]
+fn multiplicative_steps() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    fake.ok(&["up", "1.5x"]);
+    assert_eq!(fake.brightness("intel_backlight"), 450);
+    fake.ok(&["adjust", "-2x"]);
+    assert_eq!(fake.brightness("intel_backlight"), 225);
+    assert!(!fake.brightr(&["down", "0.5x"]).status.success());
+}
+
+#[test]
+fn steps_escape_zero() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 0, 1000)]) else {
+        return;
+    };
+    // With this exponent, 1% rounds to raw 0.
+    fake.ok(&["-e", "4", "up", "1"]);
+    assert_eq!(fake.brightness("intel_backlight"), 1);
+    fake.ok(&["up", "1.2x"]);
+    assert_eq!(fake.brightness("intel_backlight"), 2);
+    fake.ok(&["-m", "2", "-e", "4", "up", "1"]);
+    assert_eq!(fake.brightness("intel_backlight"), 3);
+}
+
+#[test]
+fn coarse_steps() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    fake.config("[steps]\nintel_backlight = 250\n");
+    fake.ok(&["up", "1"]);
+    assert_eq!(fake.brightness("intel_backlight"), 500);
+    fake.ok(&["down", "1"]);
+    assert_eq!(fake.brightness("intel_backlight"), 250);
+    fake.ok(&["up", "40"]);
+    assert_eq!(fake.brightness("intel_backlight"), 750);
+
+    fake.config("[steps]\nintel_backlight = 0\n");
+    let out = fake.brightr(&["get"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("must be more"));
+}
+
+#[test]
+fn adjust_takes_sign() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    fake.ok(&["adjust", "+10"]);
+    assert_eq!(fake.brightness("intel_backlight"), 400);
+    fake.ok(&["adjust", "-50r"]);
+    assert_eq!(fake.brightness("intel_backlight"), 350);
+    fake.ok(&["adjust", "5"]);
+    assert_eq!(fake.brightness("intel_backlight"), 400);
+}
+
+#[test]
+fn set_from_stdin() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    let out = fake.brightr_with_input(&["set", "-"], "250r\n");
+    assert!(out.status.success());
+    assert_eq!(fake.brightness("intel_backlight"), 250);
+    let out = fake.brightr_with_input(&["set", "-"], "lots");
+    assert!(!out.status.success());
+    assert_eq!(fake.brightness("intel_backlight"), 250);
+}
+
+#[test]
+fn stream_applies_values() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    let out =
+        fake.brightr_with_input(&["stream", "-i", "0"], "10 20\n  500r x 40");
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("bad value `x`"));
+    assert_eq!(fake.brightness("intel_backlight"), 400);
+}
+
+#[test]
+fn watch_reports_events() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    fake.attr("intel_backlight", "bl_power", "0\n");
+    // So that it's still the one reported when the other device appears.
+    fake.attr("intel_backlight", "type", "raw\n");
+    let mut child = fake
+        .command(&["status", "--watch", "-i", "500", "--json"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("can't run brightr");
+    thread::sleep(Duration::from_millis(750));
+    fake.add_device("acpi_video0", 5, 10);
+    fake.attr("intel_backlight", "max_brightness", "2000\n");
+    fake.attr("intel_backlight", "bl_power", "4\n");
+    thread::sleep(Duration::from_millis(750));
+    child.kill().unwrap();
+
+    let out = child.wait_with_output().unwrap();
+    let events = String::from_utf8(out.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect::<Vec<serde_json::Value>>();
+    // The changes may straddle a check, so don't rely on the order.
+    let find = |kind: &str| {
+        events
+            .iter()
+            .filter(|e| e["event"] == kind)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(find("device-added")[0]["device"], "acpi_video0");
+    assert_eq!(find("max-changed")[0]["new"], 2000);
+    assert_eq!(find("power")[0]["bl_power"], 4);
+    let status = find("status");
+    assert_eq!(status.len(), 2);
+    assert_eq!(status[1]["max"], 2000);
+}
+
+#[test]
+fn watch_skips_quietly() {
+    let Some(fake) =
+        Fake::new(&[("acpi_video0", 5, 10), ("intel_backlight", 300, 1000)])
+    else {
+        return;
+    };
+    fake.attr("acpi_video0", "max_brightness", "\n");
+    let mut child = fake
+        .command(&["status", "--watch", "-i", "100", "--json"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("can't run brightr");
+    thread::sleep(Duration::from_millis(500));
+    fake.attr("intel_backlight", "brightness", "700\n");
+    thread::sleep(Duration::from_millis(500));
+    child.kill().unwrap();
+
+    let out = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert_eq!(stderr.matches("skipping").count(), 1);
+    let last = String::from_utf8(out.stdout).unwrap();
+    let last = last.lines().last().unwrap();
+    let last = serde_json::from_str::<serde_json::Value>(last).unwrap();
+    assert_eq!(last["raw"], 700);
+}
+
+#[cfg(feature = "watch")]
+#[test]
+fn watch_wakes_on_change() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    // Long enough that only the watcher can explain a quick report.
+    let mut child = fake
+        .command(&["status", "--watch", "-i", "60000", "--json"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("can't run brightr");
+    thread::sleep(Duration::from_millis(500));
+    fake.attr("intel_backlight", "brightness", "700\n");
+    thread::sleep(Duration::from_millis(500));
+    child.kill().unwrap();
+
+    let out = child.wait_with_output().unwrap();
+    let reports = String::from_utf8(out.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect::<Vec<serde_json::Value>>();
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[1]["raw"], 700);
+}
+
+#[cfg(feature = "watch")]
+#[test]
+fn watch_wakes_on_hardware_change() {
+    use std::os::unix::fs::symlink;
+
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    let device = fake.dir.path().join("sys/class/backlight/intel_backlight");
+    fs::write(device.join("brightness_hw_changed"), "300\n").unwrap();
+    // The watch follows the link, so replacing the link later leaves the file
+    // being watched alone.
+    fs::rename(device.join("brightness"), device.join("old")).unwrap();
+    symlink("old", device.join("brightness")).unwrap();
+    fs::write(device.join("new"), "700\n").unwrap();
+    let mut child = fake
+        .command(&["status", "--watch", "-i", "60000", "--json"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("can't run brightr");
+    thread::sleep(Duration::from_millis(500));
+    // Pointing `brightness` somewhere else changes what it reads without
+    // touching the file being watched, much as the hardware changing by
+    // itself does, so only `brightness_hw_changed` can tell.
+    fs::remove_file(device.join("brightness")).unwrap();
+    symlink("new", device.join("brightness")).unwrap();
+    thread::sleep(Duration::from_millis(500));
+    fake.attr("intel_backlight", "brightness_hw_changed", "700\n");
+    thread::sleep(Duration::from_millis(500));
+    child.kill().unwrap();
+
+    let out = child.wait_with_output().unwrap();
+    let reports = String::from_utf8(out.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect::<Vec<serde_json::Value>>();
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[1]["raw"], 700);
+}
+
+#[test]
+fn name_picks_device() {
+    let Some(fake) =
+        Fake::new(&[("acpi_video0", 5, 10), ("intel_backlight", 300, 1000)])
+    else {
+        return;
+    };
+    fake.ok(&["-n", "intel_backlight", "set", "10"]);
+    assert_eq!(fake.brightness("intel_backlight"), 100);
+    assert_eq!(fake.brightness("acpi_video0"), 5);
+
+    let out = fake.brightr(&["-n", "intel_backlite", "get"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr)
+        .contains("did you mean `intel_backlight`?"));
+}
+
+#[test]
+fn prefers_raw_devices() {
+    let Some(fake) = Fake::new(&[
+        ("acpi_video0", 50, 100),
+        ("intel_backlight", 300, 1000),
+        ("nv_backlight", 70, 100),
+    ]) else {
+        return;
+    };
+    fake.attr("acpi_video0", "type", "firmware\n");
+    fake.attr("intel_backlight", "type", "raw\n");
+    fake.attr("nv_backlight", "type", "platform\n");
+    assert_eq!(fake.ok(&["-r", "get"]), "300/1000\n");
+    fake.attr("intel_backlight", "type", "firmware\n");
+    assert_eq!(fake.ok(&["-r", "get"]), "70/100\n");
+}
+
+#[test]
+fn name_picks_connector() {
+    let Some(fake) =
+        Fake::new(&[("amdgpu_bl0", 50, 255), ("intel_backlight", 300, 1000)])
+    else {
+        return;
+    };
+    let devices = fake.dir.path().join("sys/devices");
+    let backlights = fake.dir.path().join("sys/class/backlight");
+    // i915 puts the backlight under its connector...
+    let connector = devices.join("pci0/drm/card1/card1-eDP-1");
+    fs::create_dir_all(&connector).unwrap();
+    std::os::unix::fs::symlink(
+        &connector,
+        backlights.join("intel_backlight/device"),
+    )
+    .unwrap();
+    // ...while amdgpu puts it under the card.
+    let card = devices.join("pci1");
+    for connector in ["card0-eDP-2", "card0-HDMI-A-1"] {
+        fs::create_dir_all(card.join("drm/card0").join(connector)).unwrap();
+    }
+    std::os::unix::fs::symlink(&card, backlights.join("amdgpu_bl0/device"))
+        .unwrap();
+
+    assert_eq!(fake.ok(&["-n", "eDP-2", "-r", "get"]), "50/255\n");
+    assert_eq!(fake.ok(&["-n", "card1-eDP-1", "-r", "get"]), "300/1000\n");
+    assert!(!fake.brightr(&["-n", "HDMI-A-1", "get"]).status.success());
+    let out = fake.ok(&["-n", "amdgpu_bl0", "status"]);
+    assert!(out.contains("\nconnector:  card0-eDP-2\n"), "{out}");
+}
+
+#[test]
+fn list_on_off_device() {
+    let Some(fake) =
+        Fake::new(&[("intel_backlight", 300, 1000), ("pwm_backlight", 0, 1)])
+    else {
+        return;
+    };
+    let out = fake.ok(&["list", "--plain"]);
+    assert!(out.contains("  pwm_backlight    0/1       off\n"), "{out}");
+}
+
+#[test]
+fn list_marks_active_device() {
+    let Some(fake) =
+        Fake::new(&[("acpi_video0", 5, 10), ("intel_backlight", 300, 1000)])
+    else {
+        return;
+    };
+    let out = fake.ok(&["-n", "intel_backlight", "list"]);
+    assert_eq!(
+        out,
+        "  DEVICE           RAW       PERCENT\n  \
+         acpi_video0      5/10      50%\n\
+         * intel_backlight  300/1000  30%\n"
+    );
+    let out = fake.ok(&["-n", "acpi_video0", "list", "--plain"]);
+    assert_eq!(
+        out,
+        "* acpi_video0      5/10      50%\n  intel_backlight  300/1000  30%\n"
+    );
+}
+
+#[test]
+fn status_describes_device() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    fake.attr("intel_backlight", "type", "raw\n");
+    let parent = fake.dir.path().join("sys/devices/card0-eDP-1");
+    fs::create_dir_all(&parent).unwrap();
+    fs::write(parent.join("uevent"), "DRIVER=i915\nPCI_ID=8086:46A6\n")
+        .unwrap();
+    let device = fake.dir.path().join("sys/class/backlight/intel_backlight");
+    std::os::unix::fs::symlink(&parent, device.join("device")).unwrap();
+
+    let out = fake.ok(&["status"]);
+    assert!(
+        out.starts_with("device:     intel_backlight (raw, i915)\n"),
+        "{out}"
+    );
+    assert!(out.contains("\nsession:    3 on seat0, user alice (1000)\n"));
+}
+
+#[test]
+fn odd_sysfs_contents() {
+    let Some(fake) =
+        Fake::new(&[("acpi_video0", 5, 10), ("intel_backlight", 3, 10)])
+    else {
+        return;
+    };
+    // Brightness above max is clamped, and trailing junk ignored.
+    fake.attr("intel_backlight", "brightness", "12 junk\n");
+    assert_eq!(fake.ok(&["-n", "intel_backlight", "get"]), "100/100\n");
+    // Devices that aren't ready yet are skipped in discovery.
+    fake.attr("acpi_video0", "brightness", "");
+    assert_eq!(fake.ok(&["-r", "get"]), "10/10\n");
+    let out = fake.brightr(&["-n", "acpi_video0", "get"]);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("is empty"));
+}
+
+#[test]
+fn bench_leaves_setting_alone() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    let out = fake.ok(&["bench", "-i", "3"]);
+    assert!(out.starts_with("intel_backlight:\n  read:  p50 "), "{out}");
+    assert!(out.contains("\n  write: p50 "), "{out}");
+    assert_eq!(fake.brightness("intel_backlight"), 300);
+}
+
+#[test]
+fn export_and_import() {
+    let Some(fake) =
+        Fake::new(&[("acpi_video0", 5, 10), ("intel_backlight", 300, 1000)])
+    else {
+        return;
+    };
+    let saved = fake.ok(&["export"]);
+    assert!(saved.contains("[devices.intel_backlight]\n"), "{saved}");
+    fake.ok(&["-n", "acpi_video0", "set", "0"]);
+    fake.ok(&["-n", "intel_backlight", "set", "100"]);
+    fake.brightr_with_input(&["import", "-"], &saved);
+    assert_eq!(fake.brightness("acpi_video0"), 5);
+    assert_eq!(fake.brightness("intel_backlight"), 300);
+
+    // Devices with a different range get the same fraction of it.
+    let other = r#"{
+        "exponent": 1.0,
+        "min": 0,
+        "devices": { "intel_backlight": { "brightness": 7, "max": 10 } }
+    }"#;
+    let out = fake.brightr_with_input(&["import", "-"], other);
+    assert!(out.status.success());
+    assert_eq!(fake.brightness("intel_backlight"), 700);
+}
+
+#[test]
+fn snapshots() {
+    let Some(fake) =
+        Fake::new(&[("acpi_video0", 5, 10), ("intel_backlight", 300, 1000)])
+    else {
+        return;
+    };
+    fake.ok(&["snapshot", "save", "movie"]);
+    fake.ok(&["-n", "acpi_video0", "set", "0"]);
+    fake.ok(&["-n", "intel_backlight", "set", "100"]);
+    assert_eq!(fake.ok(&["snapshot", "list"]), "movie\n");
+    fake.ok(&["snapshot", "load", "movie"]);
+    assert_eq!(fake.brightness("acpi_video0"), 5);
+    assert_eq!(fake.brightness("intel_backlight"), 300);
+    assert!(!fake.brightr(&["snapshot", "load", "nope"]).status.success());
+
+    // With a device missing, an atomic load changes nothing.
+    fake.ok(&["-n", "intel_backlight", "set", "0"]);
+    fs::remove_dir_all(fake.dir.path().join("sys/class/backlight/acpi_video0"))
+        .unwrap();
+    let out = fake.brightr(&["snapshot", "load", "--atomic", "movie"]);
+    assert!(!out.status.success());
+    assert_eq!(fake.brightness("intel_backlight"), 0);
+    let out = fake.brightr(&["snapshot", "load", "movie"]);
+    assert!(!out.status.success());
+    assert_eq!(fake.brightness("intel_backlight"), 300);
+}
+
+#[test]
+fn test_device_restores_setting() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 1000, 1000)]) else {
+        return;
+    };
+    // Without actual_brightness, there's nothing to go on.
+    let out = fake.brightr(&["test-device"]);
+    assert!(!out.status.success());
+    let err = String::from_utf8_lossy(&out.stderr);
+    assert!(err.contains("has no actual_brightness"), "{err}");
+
+    fake.attr("intel_backlight", "actual_brightness", "1000\n");
+    fake.config("[steps]\nintel_backlight = 250\n");
+    let out = fake.ok(&["test-device"]);
+    assert!(out.contains("set 1000 -> 750 using"), "{out}");
+    assert!(out.contains("so the device responds"), "{out}");
+    assert_eq!(fake.brightness("intel_backlight"), 1000);
+
+    fake.ignore_writes("intel_backlight");
+    let out = fake.brightr(&["test-device"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("ignoring it"));
+}
+
+#[test]
+fn measure_steps_needs_readback() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    let out = fake.brightr(&["measure-steps"]);
+    assert!(!out.status.success());
+    let err = String::from_utf8_lossy(&out.stderr);
+    assert!(err.contains("has no actual_brightness"), "{err}");
+
+    // A driver that ignores writes has only one level.
+    fake.attr("intel_backlight", "actual_brightness", "300\n");
+    fake.ignore_writes("intel_backlight");
+    let out = fake.ok(&["measure-steps", "-s", "3"]);
+    assert!(out.contains("\nreadback: 1 distinct levels\n"), "{out}");
+    assert_eq!(fake.brightness("intel_backlight"), 300);
+}
+
+#[test]
+fn gnome_refuses_max_zero() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 0, 0)]) else {
+        return;
+    };
+    let out = fake.brightr(&["--backend", "gnome", "set", "0r"]);
+    assert!(!out.status.success());
+    let err = String::from_utf8_lossy(&out.stderr);
+    assert!(err.contains("out of range (maximum is 0)"), "{err}");
+}
+
+#[test]
+fn measure_steps_refuses_max_zero() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 0, 0)]) else {
+        return;
+    };
+    fake.attr("intel_backlight", "actual_brightness", "0\n");
+    let out = fake.brightr(&["measure-steps"]);
+    assert!(!out.status.success());
+    let err = String::from_utf8_lossy(&out.stderr);
+    assert!(err.contains("has a max of 0"), "{err}");
+}
+
+#[test]
+fn ramp_test_sweeps_down() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    let out = fake.brightr_with_input(&["ramp-test", "-s", "4", "-d", "0"], "");
+    assert!(out.status.success());
+    let out = String::from_utf8(out.stdout).unwrap();
+    let lines = out.lines().skip(1).collect::<Vec<_>>();
+    assert_eq!(
+        lines,
+        [
+            "  1000/1000  100%",
+            "   750/1000   75%",
+            "   500/1000   50%",
+            "   250/1000   25%",
+            "     0/1000    0%",
+            "restored 300/1000",
+        ]
+    );
+    assert_eq!(fake.brightness("intel_backlight"), 300);
+}
+
+#[test]
+fn cap_limits_setting() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    fake.config("cap = \"50%\"\n");
+    fake.ok(&["set", "90"]);
+    assert_eq!(fake.brightness("intel_backlight"), 500);
+    fake.ok(&["--override-cap", "set", "90"]);
+    assert_eq!(fake.brightness("intel_backlight"), 900);
+}
+
+#[test]
+fn low_battery_cap_falls() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    fake.config("[low_battery]\nbelow = 20\ncap = \"40r\"\n");
+    fake.battery(50., true);
+    fake.ok(&["set", "90r"]);
+    assert_eq!(fake.brightness("intel_backlight"), 90);
+    fake.battery(10., true);
+    fake.ok(&["set", "90r"]);
+    assert_eq!(fake.brightness("intel_backlight"), 40);
+
+    // With a floor, the cap falls in a straight line to it at empty.
+    fake.config("[low_battery]\nbelow = 20\ncap = \"40r\"\nfloor = \"10r\"\n");
+    fake.ok(&["set", "90r"]);
+    assert_eq!(fake.brightness("intel_backlight"), 25);
+    fake.battery(0., true);
+    fake.ok(&["set", "90r"]);
+    assert_eq!(fake.brightness("intel_backlight"), 10);
+    fake.battery(0., false);
+    fake.ok(&["set", "90r"]);
+    assert_eq!(fake.brightness("intel_backlight"), 90);
+
+    fake.config("[low_battery]\nbelow = 20\ncap = \"40%\"\nfloor = \"10r\"\n");
+    let out = fake.brightr(&["check-config"]);
+    assert!(!out.status.success());
+    let err = String::from_utf8_lossy(&out.stdout)
+        + String::from_utf8_lossy(&out.stderr);
+    assert!(err.contains("isn't in the same units"), "{err}");
+}
+
+#[test]
+fn thermal_caps_when_hot() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    let thermal = fake.dir.path().join("sys/class/thermal");
+    for (zone, kind, temp) in [(0, "acpitz", 40000), (1, "x86_pkg_temp", 0)] {
+        let zone = thermal.join(format!("thermal_zone{zone}"));
+        fs::create_dir_all(&zone).unwrap();
+        fs::write(zone.join("type"), format!("{kind}\n")).unwrap();
+        fs::write(zone.join("temp"), format!("{temp}\n")).unwrap();
+    }
+    fake.config(
+        "[thermal]\nabove = 85\nzone = \"x86_pkg_temp\"\ncap = \"50%\"\n",
+    );
+    fake.ok(&["set", "90"]);
+    assert_eq!(fake.brightness("intel_backlight"), 900);
+
+    let pkg = thermal.join("thermal_zone1/temp");
+    fs::write(pkg, "90000\n").unwrap();
+    fake.ok(&["set", "90"]);
+    assert_eq!(fake.brightness("intel_backlight"), 500);
+
+    // Only the zone asked for counts.
+    fake.config("[thermal]\nabove = 85\nzone = \"acpitz\"\ncap = \"50%\"\n");
+    fake.ok(&["set", "90"]);
+    assert_eq!(fake.brightness("intel_backlight"), 900);
+}
+
+#[test]
+fn sysfs_backend_skips_logind() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    // Dropping logind off the bus means only a direct write can work.
+    fake.logind.release_name("org.freedesktop.login1").unwrap();
+    fake.ok(&["--backend", "sysfs", "set", "20"]);
+    assert_eq!(fake.brightness("intel_backlight"), 200);
+    assert!(!fake.brightr(&["set", "40"]).status.success());
+}
+
+#[test]
+fn custom_logind_name() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    fake.logind.request_name("org.example.FakeLogin").unwrap();
+    fake.logind.release_name("org.freedesktop.login1").unwrap();
+    let run = |args: &[&str]| {
+        fake.command(args)
+            .env("BRIGHTR_LOGIND", "org.example.FakeLogin")
+            .output()
+            .unwrap()
+    };
+    assert!(run(&["--backend", "logind", "set", "20"]).status.success());
+    assert_eq!(fake.brightness("intel_backlight"), 200);
+    assert!(!fake
+        .brightr(&["--backend", "logind", "set", "40"])
+        .status
+        .success());
+}
+
+#[test]
+fn apply_boot() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    assert!(!fake.brightr(&["apply-boot"]).status.success());
+    fake.config("boot = \"40%\"\n");
+    fake.ok(&["--backend", "sysfs", "apply-boot"]);
+    assert_eq!(fake.brightness("intel_backlight"), 400);
+
+    // The unit can't run a brightr that isn't installed.
+    let out = fake.brightr(&["apply-boot", "--print-unit"]);
+    assert!(!out.status.success());
+    let err = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        err.contains("isn't installed in a system directory"),
+        "{err}"
+    );
+
+    // Nor can it see the user's config, so it warns that the system-wide one
+    // has no `boot`.
+    let binary = brightr_path();
+    let binary = binary.to_str().unwrap();
+    let out = fake.brightr(&["apply-boot", "--print-unit", "--binary", binary]);
+    assert!(out.status.success());
+    let err = String::from_utf8_lossy(&out.stderr);
+    assert!(err.contains("system.toml doesn't set `boot`"), "{err}");
+    let unit = String::from_utf8(out.stdout).unwrap();
+    assert!(unit.contains("\nType=oneshot\n"), "{unit}");
+    assert!(
+        unit.contains(" --backend sysfs --name \"intel_backlight\" "),
+        "{unit}"
+    );
+    assert!(
+        unit.contains(&format!("\nExecStart=\"{binary}\" ")),
+        "{unit}"
+    );
+    assert!(
+        unit.contains("systemd-backlight@backlight:intel_backlight.service"),
+        "{unit}"
+    );
+    assert!(unit.contains("system.toml\"\n"), "{unit}");
+    assert_eq!(fake.brightness("intel_backlight"), 400);
+
+    let system = fake.dir.path().join("system.toml");
+    fs::write(system, "boot = \"40%\"\n").unwrap();
+    let out = fake.brightr(&["apply-boot", "--print-unit", "--binary", binary]);
+    assert!(String::from_utf8_lossy(&out.stderr).is_empty());
+}
+
+#[test]
+fn xbacklight_compat() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    let link = fake.dir.path().join("xbacklight");
+    std::os::unix::fs::symlink(brightr_path(), &link).unwrap();
+    let run = |args: &[&str]| {
+        let out = fake.command_as(&link, args).output().unwrap();
+        assert!(
+            out.status.success(),
+            "xbacklight {args:?} failed:\n{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+        String::from_utf8(out.stdout).unwrap()
+    };
+    assert_eq!(run(&["-get"]), "30\n");
+    run(&["-set", "50"]);
+    assert_eq!(fake.brightness("intel_backlight"), 500);
+    run(&["-inc", "10", "-time", "200"]);
+    assert_eq!(fake.brightness("intel_backlight"), 600);
+    run(&["-ctrl", "intel_backlight", "-20.4"]);
+    assert_eq!(fake.brightness("intel_backlight"), 400);
+    run(&["=", "75"]);
+    assert_eq!(fake.brightness("intel_backlight"), 750);
+    assert_eq!(run(&[]), "75\n");
+    let out = fake.command_as(&link, &["-frob"]).output().unwrap();
+    assert!(!out.status.success());
+}
+
+#[test]
+fn light_compat() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    let link = fake.dir.path().join("light");
+    std::os::unix::fs::symlink(brightr_path(), &link).unwrap();
+    let run = |args: &[&str]| {
+        let out = fake.command_as(&link, args).output().unwrap();
+        assert!(
+            out.status.success(),
+            "light {args:?} failed:\n{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+        String::from_utf8(out.stdout).unwrap()
+    };
+    assert_eq!(run(&["-G"]), "30\n");
+    assert_eq!(run(&["-rG"]), "300\n");
+    run(&["-S", "50"]);
+    assert_eq!(fake.brightness("intel_backlight"), 500);
+    run(&["-A10"]);
+    assert_eq!(fake.brightness("intel_backlight"), 600);
+    run(&["-s", "sysfs/backlight/intel_backlight", "-r", "-U", "100"]);
+    assert_eq!(fake.brightness("intel_backlight"), 500);
+    run(&["-T", "0.5"]);
+    assert_eq!(fake.brightness("intel_backlight"), 250);
+    let out = fake.command_as(&link, &["-N", "5"]).output().unwrap();
+    assert!(!out.status.success());
+}
+
+#[test]
+fn device_names() {
+    let Some(fake) =
+        Fake::new(&[("acpi_video0", 10, 15), ("intel_backlight", 300, 1000)])
+    else {
+        return;
+    };
+    fake.config("[devices]\nlaptop = \"intel_backlight\"\n");
+    fake.ok(&["-n", "laptop", "set", "50"]);
+    assert_eq!(fake.brightness("intel_backlight"), 500);
+    assert_eq!(fake.brightness("acpi_video0"), 10);
+
+    fake.config("[devices]\nlaptop = \"nope\"\n");
+    let out = fake.brightr(&["check-config"]);
+    assert!(!out.status.success());
+    let err = String::from_utf8(out.stderr).unwrap();
+    // Problems say where they are.
+    assert!(
+        err.contains("config.toml:2:1: device name `laptop` refers to `nope`"),
+        "{err}"
+    );
+
+    // Every other place a device is named gets checked too, with suggestions
+    // for typos.
+    let host = fs::read_to_string("/proc/sys/kernel/hostname").unwrap();
+    fake.config(&format!(
+        "[host.\"{}\"]\n\
+         device = \"intel_backlite\"\n\
+         [host.elsewhere]\n\
+         device = \"radeon_bl0\"\n\
+         [steps]\n\
+         acpi_video1 = 5\n",
+        host.trim()
+    ));
+    let out = fake.brightr(&["check-config"]);
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(
+        err.contains(&format!(
+            "config.toml:2:1: [host.\"{}\"] device is `intel_backlite`, which \
+             isn't on this machine; did you mean `intel_backlight`?",
+            host.trim()
+        )),
+        "{err}"
+    );
+    assert!(
+        err.contains(
+            "config.toml:6:1: [steps] has `acpi_video1`, which isn't on this \
+             machine; did you mean `acpi_video0`?"
+        ),
+        "{err}"
+    );
+    assert!(err.contains("found 2 problem(s)"), "{err}");
+    fake.config("device = \"laptop\"\n[devices]\nlaptop = \"acpi_video0\"\n");
+    fake.ok(&["check-config"]);
+}
+
+#[test]
+fn broken_config_can_be_fixed() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    fake.config("cap = \"50%\"\n[alias\n");
+    let out = fake.brightr(&["get"]);
+    assert!(!out.status.success());
+
+    // Help and the commands for sorting the file out still work.
+    fake.ok(&["--help"]);
+    let out = fake.brightr(&["check-config"]);
+    assert!(!out.status.success());
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(err.contains("problem: bad config file"), "{err}");
+    assert!(err.contains("line 2, column 7"), "{err}");
+    fake.ok(&["init-config", "--force"]);
+    fake.ok(&["check-config"]);
+    fake.ok(&["get"]);
+}
+
+#[test]
+fn system_config() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    let system = fake.dir.path().join("system.toml");
+    fs::write(&system, "cap = \"50%\"\n[alias]\nhalf = \"set 50\"\n").unwrap();
+    fake.ok(&["set", "90"]);
+    assert_eq!(fake.brightness("intel_backlight"), 500);
+
+    // The user's settings win, but sections are merged.
+    fake.config("cap = \"80%\"\n[alias]\nfull = \"set 100\"\n");
+    fake.ok(&["full"]);
+    assert_eq!(fake.brightness("intel_backlight"), 800);
+    fake.ok(&["-r", "set", "0"]);
+    fake.ok(&["half"]);
+    assert_eq!(fake.brightness("intel_backlight"), 500);
+
+    fs::write(&system, "cap = \"lots\"\n").unwrap();
+    let out = fake.brightr(&["get"]);
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(err.contains("system.toml"), "{err}");
+}
+
+#[test]
+fn explains_environment() {
+    let Some(fake) = Fake::new(&[]) else {
+        return;
+    };
+    let run = |var: &str, value: &str| {
+        let out = fake
+            .command(&["--json-errors", "get"])
+            .env(var, value)
+            .output()
+            .unwrap();
+        assert_eq!(out.status.code(), Some(5));
+        let report: serde_json::Value =
+            serde_json::from_slice(&out.stderr).unwrap();
+        report
+    };
+    let report = run("SSH_CONNECTION", "10.0.0.2 50000 10.0.0.1 22");
+    assert_eq!(report["code"], "remote");
+    assert!(report["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("no backlight hardware on this remote machine: "));
+    assert_eq!(run("DISPLAY", "localhost:10.0")["code"], "remote");
+    assert_eq!(run("container", "podman")["code"], "container");
+}
+
+#[test]
+fn host_config() {
+    let Some(fake) =
+        Fake::new(&[("acpi_video0", 10, 15), ("intel_backlight", 300, 1000)])
+    else {
+        return;
+    };
+    let host = fs::read_to_string("/proc/sys/kernel/hostname").unwrap();
+    let host = host.trim();
+    fake.config(&format!(
+        "exponent = 3\n\
+         [host.\"not-{host}\"]\n\
+         device = \"acpi_video0\"\n\
+         [host.\"{host}\"]\n\
+         device = \"intel_backlight\"\n\
+         exponent = 2\n"
+    ));
+    fake.ok(&["set", "50"]);
+    assert_eq!(fake.brightness("intel_backlight"), 250);
+    assert_eq!(fake.brightness("acpi_video0"), 10);
+    // The command line still wins.
+    fake.ok(&["-e", "1", "set", "50"]);
+    assert_eq!(fake.brightness("intel_backlight"), 500);
+    // A default device doesn't narrow commands that cover every device.
+    let saved = fake.ok(&["export"]);
+    assert!(saved.contains("[devices.acpi_video0]\n"), "{saved}");
+
+    fake.config("[host.x]\nexponent = \"steep\"\n");
+    let out = fake.brightr(&["get"]);
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(err.contains("[host.\"x\"]"), "{err}");
+}
+
+#[test]
+fn custom_scale() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    assert_eq!(fake.ok(&["--scale", "0:255", "get"]), "77/255\n");
+    fake.ok(&["--scale", "0:255", "set", "51"]);
+    assert_eq!(fake.brightness("intel_backlight"), 200);
+    fake.ok(&["--scale", "0:10", "up", "1"]);
+    assert_eq!(fake.brightness("intel_backlight"), 300);
+    // Suffixes still win.
+    fake.ok(&["--scale", "0:255", "set", "50%"]);
+    assert_eq!(fake.brightness("intel_backlight"), 500);
+    assert!(!fake.brightr(&["--scale", "1:255", "get"]).status.success());
+}
+
+#[test]
+fn no_dbus() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    let run = |args: &[&str]| {
+        fake.command(args)
+            .env("DBUS_SYSTEM_BUS_ADDRESS", "unix:path=/nonexistent")
+            .output()
+            .unwrap()
+    };
+    let out = run(&["--no-dbus", "status"]);
+    let out = String::from_utf8(out.stdout).unwrap();
+    assert!(out.contains("\nbackend:    sysfs\n"), "{out}");
+    assert!(!out.contains("session:"), "{out}");
+    // Changes still work, by writing to sysfs directly.
+    assert!(!run(&["set", "20"]).status.success());
+    assert!(run(&["--no-dbus", "set", "20"]).status.success());
+    assert_eq!(fake.brightness("intel_backlight"), 200);
+}
+
+#[test]
+fn json_errors() {
+    let Some(fake) = Fake::new(&[("intel_backlight", 300, 1000)]) else {
+        return;
+    };
+    let out = fake.brightr(&["--json-errors", "-n", "nope", "get"]);
+    assert!(!out.status.success());
+    let report: serde_json::Value =
+        serde_json::from_slice(&out.stderr).unwrap();
+    assert_eq!(report["code"], "no-such-device");
+    assert_eq!(report["error_code"], 3);
+    assert_eq!(report["device"], "nope");
+}